@@ -5,11 +5,11 @@
 //! Provides a macro to color text as well as sturcts to get their
 //! raw ansi codes.
 
-use std::fmt;
+use std::{env, fmt, io, str::FromStr};
 
 /// Shortcut to produce a String colored with one or more colors.
 /// Example:
-/// ```
+/// ```ignore
 ///   let s = color_string!("Red string", Red);
 ///   let x = color_string!("Hyperlink-ish", Blue, Underline);
 macro_rules! color_string {
@@ -24,7 +24,7 @@ macro_rules! color_string {
 }
 
 /// Shortcut to produce a color's ANSI escape code. Don't forget to Reset!
-/// ```
+/// ```ignore
 ///   let mut o = String::new();
 ///   o.push_str(color!(Blue));
 ///   o.push_str(color!(Underline));
@@ -38,7 +38,7 @@ macro_rules! color {
 
 /// Create a color:: struct that can be used with format!.
 /// Example:
-/// ```
+/// ```ignore
 ///   define_color(Red, 91);
 ///   define_color(Reset, 0);
 ///
@@ -101,8 +101,134 @@ define_color!(MagentaBG, 45);
 define_color!(CyanBG, 46);
 define_color!(WhiteBG, 47);
 
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Depth {
+    /// Bold, underline and reverse video, but no colors.
+    Mono,
+    /// The 16 basic colors.
+    Basic,
+    /// The 256 color palette.
+    Fixed,
+    /// 24-bit color.
+    Rgb,
+}
+
+impl Depth {
+    /// Guess from the environment, like most terminal programs do.
+    pub fn detect() -> Depth {
+        let var = |name| env::var(name).unwrap_or_default();
+        Depth::from_env(
+            env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+            &var("TERM"),
+            &var("COLORTERM"),
+        )
+    }
+
+    /// `NO_COLOR` set to anything means no colors. So does
+    /// `TERM=dumb`, or no `TERM` at all.
+    fn from_env(no_color: bool, term: &str, colorterm: &str) -> Depth {
+        if no_color || term.is_empty() || term == "dumb" {
+            Depth::Mono
+        } else if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            Depth::Rgb
+        } else if term.contains("256color") {
+            Depth::Fixed
+        } else {
+            Depth::Basic
+        }
+    }
+}
+
+/// Whether to use colors: `--color=auto|always|never`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Whatever the terminal says it can do.
+    Auto,
+    /// Colors even with `NO_COLOR` or `TERM=dumb`.
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// How many colors to draw with.
+    pub fn depth(self) -> Depth {
+        match self {
+            ColorChoice::Auto => Depth::detect(),
+            ColorChoice::Always => {
+                let term = env::var("TERM").unwrap_or_default();
+                let colorterm = env::var("COLORTERM").unwrap_or_default();
+                Depth::from_env(false, &term, &colorterm).max(Depth::Basic)
+            }
+            ColorChoice::Never => Depth::Mono,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<ColorChoice> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(io::Error::other(format!(
+                "unknown color choice: {} (try auto, always, or never)",
+                s
+            ))),
+        }
+    }
+}
+
+/// The closest color to `(r, g, b)` in the 256 color palette: the
+/// 6x6x6 cube, or the grey ramp for greys.
+pub fn rgb_to_fixed(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((r - 3) / 10).min(23),
+        };
+    }
+    let level = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (v - 35) / 40,
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// The closest of the 16 basic colors to a 256 color palette entry.
+pub fn fixed_to_basic(n: u8) -> u8 {
+    match n {
+        0..=15 => n,
+        232..=255 => match (n - 232) * 10 + 8 {
+            0..=63 => 0,
+            64..=159 => 8,
+            160..=223 => 7,
+            _ => 15,
+        },
+        _ => {
+            let n = n - 16;
+            let (r, g, b) = (n / 36, n / 6 % 6, n % 6);
+            let hue = (r >= 3) as u8 | ((g >= 3) as u8) << 1 | ((b >= 3) as u8) << 2;
+            let max = r.max(g).max(b);
+            match hue {
+                0 if max >= 2 => 8,
+                0 => 0,
+                7 if max < 5 => 7,
+                _ if max >= 5 => hue + 8,
+                _ => hue,
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_colors() {
         assert_eq!(color_string!("Error", Red), "\x1b[91mError\x1b[0m");
@@ -115,4 +241,43 @@ mod tests {
             "\x1b[95;4;1;44mSuper-duper-fancy-pants\x1b[0m"
         )
     }
+
+    #[test]
+    fn test_color_codes() {
+        let mut o = String::new();
+        o.push_str(color!(Blue));
+        o.push_str(color!(Underline));
+        o.push_str("Hyperlinkish.");
+        o.push_str(color!(Reset));
+        assert_eq!("\x1b[94m\x1b[4mHyperlinkish.\x1b[0m", o);
+        assert_eq!("\x1b[91mError\x1b[0m", format!("{}Error{}", Red, Reset));
+        assert_eq!("45", MagentaBG::code());
+    }
+
+    #[test]
+    fn test_depth() {
+        assert_eq!(Depth::Mono, Depth::from_env(true, "xterm-256color", ""));
+        assert_eq!(Depth::Mono, Depth::from_env(false, "dumb", ""));
+        assert_eq!(Depth::Mono, Depth::from_env(false, "", ""));
+        assert_eq!(Depth::Basic, Depth::from_env(false, "xterm", ""));
+        assert_eq!(Depth::Fixed, Depth::from_env(false, "screen-256color", ""));
+        assert_eq!(Depth::Rgb, Depth::from_env(false, "xterm", "truecolor"));
+        assert_eq!(Depth::Rgb, Depth::from_env(false, "xterm-direct", ""));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+        assert_eq!(Depth::Mono, ColorChoice::Never.depth());
+    }
+
+    #[test]
+    fn test_downsample() {
+        assert_eq!(16, rgb_to_fixed(0, 0, 0));
+        assert_eq!(231, rgb_to_fixed(255, 255, 255));
+        assert_eq!(244, rgb_to_fixed(128, 128, 128));
+        assert_eq!(208, rgb_to_fixed(255, 135, 0));
+        assert_eq!(9, fixed_to_basic(196));
+        assert_eq!(1, fixed_to_basic(124));
+        assert_eq!(10, fixed_to_basic(46));
+        assert_eq!(8, fixed_to_basic(244));
+        assert_eq!(15, fixed_to_basic(231));
+        assert_eq!(5, fixed_to_basic(5));
+    }
 }
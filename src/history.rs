@@ -0,0 +1,226 @@
+//! Remembers which hosts you launch, so the ones you use most and
+//! most recently ("frecency") can be listed first.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Every launch we've recorded, oldest first. Stored one per line as
+/// `timestamp<TAB>action<TAB>alias`.
+#[derive(Debug, Default)]
+pub struct History {
+    pub path: PathBuf,
+    pub visits: Vec<Visit>,
+}
+
+/// A single launch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visit {
+    pub time: u64,
+    pub action: String,
+    pub alias: String,
+}
+
+/// Frecency score for each alias we've visited. Higher is better.
+pub type Frecency = HashMap<String, u64>;
+
+impl History {
+    /// `$XDG_STATE_HOME/shy/history`, or `~/.local/state/shy/history`.
+    pub fn default_path() -> PathBuf {
+        let state = env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .unwrap_or_else(|| {
+                PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".local/state")
+            });
+        state.join("shy").join("history")
+    }
+
+    /// Load the history file. A missing file is just an empty history,
+    /// and lines we can't read are skipped.
+    pub fn load(path: PathBuf) -> io::Result<History> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let visits = text.lines().filter_map(Visit::parse).collect();
+        Ok(History { path, visits })
+    }
+
+    /// Record a launch, appending it to the history file.
+    pub fn record(&mut self, alias: &str, action: &str, time: u64) -> io::Result<()> {
+        let visit = Visit {
+            time,
+            action: action.into(),
+            alias: alias.into(),
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", visit)?;
+        self.visits.push(visit);
+        Ok(())
+    }
+
+    /// Forget everything.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.visits.clear();
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Forget launches older than `before`, returning how many were
+    /// removed.
+    pub fn prune(&mut self, before: u64) -> io::Result<usize> {
+        let len = self.visits.len();
+        self.visits.retain(|v| v.time >= before);
+        let removed = len - self.visits.len();
+        if removed > 0 {
+            let text = self
+                .visits
+                .iter()
+                .map(|v| format!("{}\n", v))
+                .collect::<String>();
+            fs::write(&self.path, text)?;
+        }
+        Ok(removed)
+    }
+
+    /// Score each alias by how often and how recently it was launched.
+    /// Each launch is worth less the older it gets.
+    pub fn frecency(&self, now: u64) -> Frecency {
+        let mut scores = Frecency::new();
+        for visit in &self.visits {
+            let age = now.saturating_sub(visit.time) / DAY;
+            let weight = match age {
+                0..=3 => 100,
+                4..=14 => 70,
+                15..=31 => 50,
+                32..=90 => 30,
+                _ => 10,
+            };
+            *scores.entry(visit.alias.clone()).or_default() += weight;
+        }
+        scores
+    }
+}
+
+impl Visit {
+    /// Parse a line from the history file.
+    fn parse(line: &str) -> Option<Visit> {
+        let mut parts = line.splitn(3, '\t');
+        let time = parts.next()?.parse().ok()?;
+        let action = parts.next()?.to_string();
+        let alias = parts.next().filter(|a| !a.is_empty())?.to_string();
+        Some(Visit {
+            time,
+            action,
+            alias,
+        })
+    }
+}
+
+impl std::fmt::Display for Visit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\t{}\t{}", self.time, self.action, self.alias)
+    }
+}
+
+/// Seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Convert a number of days to seconds, for pruning.
+pub fn days(days: u64) -> u64 {
+    days.saturating_mul(DAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir()
+            .join(format!("shy-test-{}-{}", std::process::id(), name))
+            .join("history")
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let path = temp_path("record");
+        let mut history = History::load(path.clone()).unwrap();
+        assert!(history.visits.is_empty());
+
+        history.record("web", "ssh", 100).unwrap();
+        history.record("db", "sftp", 200).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"garbage\n")
+            .unwrap();
+
+        let loaded = History::load(path.clone()).unwrap();
+        assert_eq!(history.visits, loaded.visits);
+        assert_eq!("db", loaded.visits[1].alias);
+        assert_eq!("sftp", loaded.visits[1].action);
+
+        let mut loaded = loaded;
+        assert_eq!(1, loaded.prune(150).unwrap());
+        assert_eq!(1, History::load(path.clone()).unwrap().visits.len());
+
+        loaded.clear().unwrap();
+        assert!(!path.exists());
+        fs::remove_dir(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_days() {
+        assert_eq!(2 * DAY, days(2));
+        assert_eq!(u64::MAX, days(u64::MAX / 2));
+    }
+
+    #[test]
+    fn test_frecency() {
+        let now = days(365);
+        let visit = |alias: &str, age: u64| Visit {
+            time: now - days(age),
+            action: "ssh".into(),
+            alias: alias.into(),
+        };
+        let history = History {
+            path: PathBuf::new(),
+            visits: vec![
+                visit("old", 200),
+                visit("old", 200),
+                visit("recent", 1),
+                visit("often", 20),
+                visit("often", 20),
+                visit("often", 10),
+            ],
+        };
+
+        let scores = history.frecency(now);
+        assert_eq!(Some(&20), scores.get("old"));
+        assert_eq!(Some(&100), scores.get("recent"));
+        assert_eq!(Some(&170), scores.get("often"));
+        assert_eq!(None, scores.get("never"));
+    }
+}
@@ -0,0 +1,436 @@
+//! Which key does what, in each mode. The defaults can be changed in
+//! the settings file:
+//!
+//! ```toml
+//! [keys.nav]
+//! G = "bottom"
+//! s = "none"
+//! ```
+
+use {
+    crate::tui::Mode,
+    indexmap::IndexMap,
+    std::{fmt, str::FromStr},
+    termion::event::Key,
+};
+
+/// Something a key can do. Not every command means something in
+/// every mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Quit,
+    /// Leave the menu, prompt or warnings. While searching, clear the
+    /// search, then go back to Nav mode.
+    Back,
+    Search,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Refresh,
+    Warnings,
+    /// Switch between config and frecency order.
+    Sort,
+    /// Switch between grouping by group, file, or not at all.
+    View,
+    Preview,
+    NextTag,
+    Collapse,
+    Expand,
+    /// Connect to the selected host, or run the selected action.
+    Launch,
+    /// Open the action menu.
+    Menu,
+    DeleteChar,
+    DeleteWord,
+    /// Clear the search or prompt.
+    Clear,
+}
+
+impl Command {
+    /// Every command, in the order `--print-keymap` lists them.
+    pub const ALL: [Command; 22] = [
+        Command::Quit,
+        Command::Back,
+        Command::Search,
+        Command::Up,
+        Command::Down,
+        Command::PageUp,
+        Command::PageDown,
+        Command::Top,
+        Command::Bottom,
+        Command::Refresh,
+        Command::Warnings,
+        Command::Sort,
+        Command::View,
+        Command::Preview,
+        Command::NextTag,
+        Command::Collapse,
+        Command::Expand,
+        Command::Launch,
+        Command::Menu,
+        Command::DeleteChar,
+        Command::DeleteWord,
+        Command::Clear,
+    ];
+
+    /// The name used in the settings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Quit => "quit",
+            Command::Back => "back",
+            Command::Search => "search",
+            Command::Up => "up",
+            Command::Down => "down",
+            Command::PageUp => "page-up",
+            Command::PageDown => "page-down",
+            Command::Top => "top",
+            Command::Bottom => "bottom",
+            Command::Refresh => "refresh",
+            Command::Warnings => "warnings",
+            Command::Sort => "sort",
+            Command::View => "view",
+            Command::Preview => "preview",
+            Command::NextTag => "next-tag",
+            Command::Collapse => "collapse",
+            Command::Expand => "expand",
+            Command::Launch => "launch",
+            Command::Menu => "menu",
+            Command::DeleteChar => "delete-char",
+            Command::DeleteWord => "delete-word",
+            Command::Clear => "clear",
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Command, String> {
+        Command::ALL
+            .iter()
+            .find(|c| c.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown command: {}", s))
+    }
+}
+
+/// The keys for each mode. Keys that aren't bound type text in Search
+/// mode and the prompt, and pick actions in the action menu.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    pub nav: IndexMap<Key, Command>,
+    pub search: IndexMap<Key, Command>,
+    /// The action menu.
+    pub menu: IndexMap<Key, Command>,
+    pub prompt: IndexMap<Key, Command>,
+    pub warnings: IndexMap<Key, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        use Command::*;
+        Keymap {
+            nav: bindings(&[
+                ("q", Quit),
+                ("esc", Quit),
+                ("ctrl-c", Quit),
+                ("i", Search),
+                ("s", Search),
+                ("/", Search),
+                ("f", Search),
+                ("up", Up),
+                ("ctrl-p", Up),
+                ("k", Up),
+                ("down", Down),
+                ("ctrl-n", Down),
+                ("j", Down),
+                ("pageup", PageUp),
+                ("-", PageUp),
+                ("pagedown", PageDown),
+                ("space", PageDown),
+                ("home", Top),
+                ("end", Bottom),
+                ("r", Refresh),
+                ("f5", Refresh),
+                ("w", Warnings),
+                ("o", Sort),
+                ("g", View),
+                ("p", Preview),
+                ("f2", Preview),
+                ("t", NextTag),
+                ("left", Collapse),
+                ("right", Expand),
+                ("enter", Launch),
+                ("tab", Menu),
+            ]),
+            search: bindings(&[
+                ("esc", Back),
+                ("ctrl-c", Back),
+                ("up", Up),
+                ("ctrl-p", Up),
+                ("down", Down),
+                ("ctrl-n", Down),
+                ("pageup", PageUp),
+                ("pagedown", PageDown),
+                ("f2", Preview),
+                ("enter", Launch),
+                ("tab", Menu),
+                ("backspace", DeleteChar),
+                ("ctrl-w", DeleteWord),
+                ("ctrl-u", Clear),
+            ]),
+            menu: bindings(&[
+                ("esc", Back),
+                ("ctrl-c", Back),
+                ("q", Back),
+                ("tab", Back),
+                ("up", Up),
+                ("ctrl-p", Up),
+                ("k", Up),
+                ("down", Down),
+                ("ctrl-n", Down),
+                ("j", Down),
+                ("enter", Launch),
+            ]),
+            prompt: bindings(&[
+                ("esc", Back),
+                ("ctrl-c", Back),
+                ("enter", Launch),
+                ("backspace", DeleteChar),
+                ("ctrl-w", DeleteWord),
+                ("ctrl-u", Clear),
+            ]),
+            warnings: bindings(&[
+                ("esc", Back),
+                ("ctrl-c", Back),
+                ("q", Back),
+                ("w", Back),
+                ("up", Up),
+                ("ctrl-p", Up),
+                ("down", Down),
+                ("ctrl-n", Down),
+            ]),
+        }
+    }
+}
+
+/// Build a mode's bindings from key names.
+fn bindings(keys: &[(&str, Command)]) -> IndexMap<Key, Command> {
+    keys.iter()
+        .map(|(name, command)| (parse_key(name).expect("bad default key"), *command))
+        .collect()
+}
+
+impl Keymap {
+    /// What a key does in the given mode, if anything.
+    pub fn get(&self, mode: &Mode, key: Key) -> Option<Command> {
+        self.mode(mode)?.get(&key).copied()
+    }
+
+    /// Bind a key to a command in a mode, both by name, like the
+    /// settings file does. `none` unbinds the key.
+    pub fn bind(&mut self, mode: &str, key: &str, command: &str) -> Result<(), String> {
+        let bindings = match mode {
+            "nav" => &mut self.nav,
+            "search" => &mut self.search,
+            "menu" => &mut self.menu,
+            "prompt" => &mut self.prompt,
+            "warnings" => &mut self.warnings,
+            _ => {
+                return Err(format!(
+                    "unknown mode: {} (try nav, search, menu, prompt, or warnings)",
+                    mode
+                ))
+            }
+        };
+        let key = parse_key(key)?;
+        if command == "none" {
+            bindings.shift_remove(&key);
+        } else {
+            bindings.insert(key, command.parse()?);
+        }
+        Ok(())
+    }
+
+    /// The bindings for a mode.
+    fn mode(&self, mode: &Mode) -> Option<&IndexMap<Key, Command>> {
+        match mode {
+            Mode::Nav => Some(&self.nav),
+            Mode::Search => Some(&self.search),
+            Mode::Actions => Some(&self.menu),
+            Mode::Prompt(_) => Some(&self.prompt),
+            Mode::Warnings => Some(&self.warnings),
+            Mode::Quit | Mode::Launch(_) => None,
+        }
+    }
+}
+
+/// The keymap as settings, ready to paste into the settings file.
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modes = [
+            ("nav", &self.nav),
+            ("search", &self.search),
+            ("menu", &self.menu),
+            ("prompt", &self.prompt),
+            ("warnings", &self.warnings),
+        ];
+        for (i, (name, bindings)) in modes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[keys.{}]", name)?;
+            // grouped by command, so every way to do something is together
+            let mut bindings = bindings.iter().collect::<Vec<_>>();
+            let order = |c: &Command| Command::ALL.iter().position(|a| a == c);
+            bindings.sort_by_key(|(_, command)| order(command));
+            for (key, command) in bindings {
+                let key = key_name(*key);
+                let key = key.replace('\\', "\\\\").replace('"', "\\\"");
+                writeln!(f, "\"{}\" = \"{}\"", key, command.name())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a key name like `q`, `G`, `ctrl-w`, `alt-x`, `enter`, or `f5`.
+/// Named keys ignore case, but single characters don't.
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Char(c));
+    }
+
+    let lower = name.to_lowercase();
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(c) = lower.strip_prefix("ctrl-").and_then(single) {
+        return Ok(Key::Ctrl(c));
+    }
+    if let Some(c) = name
+        .get(4..)
+        .filter(|_| lower.starts_with("alt-"))
+        .and_then(single)
+    {
+        return Ok(Key::Alt(c));
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return Ok(Key::F(n));
+    }
+
+    Ok(match lower.as_ref() {
+        "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        "backspace" => Key::Backspace,
+        "esc" | "escape" => Key::Esc,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backtab" => Key::BackTab,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        _ => return Err(format!("unknown key: {}", name)),
+    })
+}
+
+/// The name `parse_key` understands for a key.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char('\n') => "enter".into(),
+        Key::Char('\t') => "tab".into(),
+        Key::Char(' ') => "space".into(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::Backspace => "backspace".into(),
+        Key::Esc => "esc".into(),
+        Key::Up => "up".into(),
+        Key::Down => "down".into(),
+        Key::Left => "left".into(),
+        Key::Right => "right".into(),
+        Key::Home => "home".into(),
+        Key::End => "end".into(),
+        Key::PageUp => "pageup".into(),
+        Key::PageDown => "pagedown".into(),
+        Key::BackTab => "backtab".into(),
+        Key::Delete => "delete".into(),
+        Key::Insert => "insert".into(),
+        _ => "null".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        for name in &[
+            "q", "G", "-", "/", "enter", "tab", "space", "ctrl-w", "alt-X", "f5", "pageup", "esc",
+        ] {
+            assert_eq!(*name, key_name(parse_key(name).unwrap()));
+        }
+        assert_eq!(Ok(Key::Ctrl('w')), parse_key("Ctrl-W"));
+        assert_eq!(Ok(Key::Esc), parse_key("ESC"));
+        assert!(parse_key("ctrl-").is_err());
+        assert!(parse_key("hyper-x").is_err());
+        assert!(parse_key("").is_err());
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut keymap = Keymap::default();
+        assert_eq!(
+            Some(Command::Search),
+            keymap.get(&Mode::Nav, Key::Char('s'))
+        );
+        assert_eq!(None, keymap.get(&Mode::Search, Key::Char('s')));
+        assert_eq!(Some(Command::Back), keymap.get(&Mode::Search, Key::Esc));
+
+        keymap.bind("nav", "G", "bottom").unwrap();
+        keymap.bind("nav", "s", "none").unwrap();
+        keymap.bind("search", "ctrl-j", "down").unwrap();
+        assert_eq!(
+            Some(Command::Bottom),
+            keymap.get(&Mode::Nav, Key::Char('G'))
+        );
+        assert_eq!(None, keymap.get(&Mode::Nav, Key::Char('s')));
+        assert_eq!(
+            Some(Command::Down),
+            keymap.get(&Mode::Search, Key::Ctrl('j'))
+        );
+
+        assert!(keymap.bind("visual", "j", "down").is_err());
+        assert!(keymap.bind("nav", "j", "jump").is_err());
+        assert!(keymap.bind("nav", "hyper-j", "down").is_err());
+    }
+
+    #[test]
+    fn test_print() {
+        let printed = Keymap::default().to_string();
+        assert!(printed.starts_with("[keys.nav]\n\"q\" = \"quit\"\n\"esc\" = \"quit\"\n"));
+        assert!(printed.contains("\n[keys.warnings]\n"));
+        assert!(printed.contains("\"space\" = \"page-down\"\n"));
+
+        // it reads back in as the same keymap
+        let settings = crate::settings::Settings::parse(&printed);
+        assert!(settings.warnings.is_empty(), "{:?}", settings.warnings);
+        assert_eq!(Keymap::default(), settings.keymap);
+    }
+}
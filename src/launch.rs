@@ -0,0 +1,484 @@
+//! Launch templates: what shy runs once you pick a host, like
+//! `ssh {alias}`, `mosh {alias}` or `et {user}@{hostname}:{port}`.
+
+use {
+    crate::ssh_config::{matches_patterns, split_args, HostEntry, Pattern},
+    std::{io, process::Command, str::FromStr},
+};
+
+/// What to do with a host once it's picked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Run the launch template, `ssh {alias} {args}` by default.
+    Ssh,
+    /// Open an sftp session.
+    Sftp,
+    /// Copy a local file to the host's home directory.
+    Scp(String),
+    /// Connect with mosh instead of ssh.
+    Mosh,
+    /// Open a `-L` port forward, like `8080:localhost:80`.
+    Forward(String),
+    /// Copy the hostname to the clipboard.
+    CopyHostname,
+    /// Run a one-off command on the host.
+    Command(String),
+}
+
+/// A host and what to do with it, picked in the TUI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launch {
+    pub alias: String,
+    pub action: Action,
+}
+
+/// The command shy runs when there's no other template.
+pub const DEFAULT_TEMPLATE: &str = "ssh {alias} {args}";
+
+/// A command to run, split into words. Each word can contain
+/// placeholders like `{hostname}` that are filled from the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    words: Vec<Vec<Part>>,
+}
+
+/// A piece of a word in a template.
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// Placeholders a template can use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Alias,
+    Hostname,
+    User,
+    Port,
+    Args,
+}
+
+/// Picks the template for a host: the first `--exec-for` pattern that
+/// matches the alias, otherwise the default.
+#[derive(Debug, Clone, Default)]
+pub struct Launcher {
+    pub default: Template,
+    pub rules: Vec<(Vec<Pattern>, Template)>,
+}
+
+impl Launcher {
+    /// The template to use for the given alias.
+    pub fn template(&self, alias: &str) -> &Template {
+        self.rules
+            .iter()
+            .find(|(patterns, _)| matches_patterns(patterns, alias))
+            .map(|(_, template)| template)
+            .unwrap_or(&self.default)
+    }
+}
+
+impl Action {
+    /// Every action, in the order the menu shows them. Actions that
+    /// need input have it empty.
+    pub fn all() -> Vec<Action> {
+        vec![
+            Action::Ssh,
+            Action::Sftp,
+            Action::Scp(String::new()),
+            Action::Mosh,
+            Action::Forward(String::new()),
+            Action::CopyHostname,
+            Action::Command(String::new()),
+        ]
+    }
+
+    /// The key that picks this action in the menu, or with Alt.
+    pub fn key(&self) -> char {
+        match self {
+            Action::Ssh => 's',
+            Action::Sftp => 'f',
+            Action::Scp(_) => 'c',
+            Action::Mosh => 'm',
+            Action::Forward(_) => 'l',
+            Action::CopyHostname => 'y',
+            Action::Command(_) => 'x',
+        }
+    }
+
+    /// The action for a menu key.
+    pub fn from_key(key: char) -> Option<Action> {
+        Action::all().into_iter().find(|a| a.key() == key)
+    }
+
+    /// Short name, for the history file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Ssh => "ssh",
+            Action::Sftp => "sftp",
+            Action::Scp(_) => "scp",
+            Action::Mosh => "mosh",
+            Action::Forward(_) => "forward",
+            Action::CopyHostname => "copy",
+            Action::Command(_) => "command",
+        }
+    }
+
+    /// Short description for the menu.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Ssh => "ssh",
+            Action::Sftp => "sftp",
+            Action::Scp(_) => "scp a file",
+            Action::Mosh => "mosh",
+            Action::Forward(_) => "port forward",
+            Action::CopyHostname => "copy hostname",
+            Action::Command(_) => "run a command",
+        }
+    }
+
+    /// What to ask for, if this action needs input before it runs.
+    pub fn prompt(&self) -> Option<&'static str> {
+        match self {
+            Action::Scp(_) => Some("file to copy: "),
+            Action::Forward(_) => Some("forward (port:host:port): "),
+            Action::Command(_) => Some("command: "),
+            _ => None,
+        }
+    }
+
+    /// The same action with its input filled in.
+    pub fn with_input(self, input: &str) -> Action {
+        match self {
+            Action::Scp(_) => Action::Scp(input.into()),
+            Action::Forward(_) => Action::Forward(input.into()),
+            Action::Command(_) => Action::Command(input.into()),
+            action => action,
+        }
+    }
+
+    /// The command to run for this action. Extra arguments only go
+    /// to the plain ssh action, which uses the launch template.
+    /// Copying the hostname happens in the TUI, so it has none.
+    pub fn command(
+        &self,
+        launcher: &Launcher,
+        host: &HostEntry,
+        args: &[String],
+    ) -> io::Result<Option<Command>> {
+        let alias = &host.name;
+        let argv = match self {
+            Action::Ssh => return launcher.template(alias).command(host, args).map(Some),
+            Action::CopyHostname => return Ok(None),
+            Action::Sftp => vec!["sftp".into(), alias.clone()],
+            Action::Scp(path) => vec!["scp".into(), path.clone(), format!("{}:", alias)],
+            Action::Mosh => vec!["mosh".into(), alias.clone()],
+            Action::Forward(spec) => vec![
+                "ssh".into(),
+                "-N".into(),
+                "-L".into(),
+                spec.clone(),
+                alias.clone(),
+            ],
+            Action::Command(cmd) => vec!["ssh".into(), alias.clone(), cmd.clone()],
+        };
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        Ok(Some(cmd))
+    }
+}
+
+/// The escape sequence that asks the terminal to put `text` on the
+/// clipboard (OSC 52). Works over ssh and in most terminals.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+/// Standard base64, with padding.
+fn base64(bytes: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        DEFAULT_TEMPLATE.parse().expect("bad default template")
+    }
+}
+
+impl FromStr for Template {
+    type Err = io::Error;
+
+    /// Parse a template, making sure every placeholder is one we know
+    /// how to fill in. Words are split like ssh config arguments, so
+    /// quotes keep spaces in one word. Use `{{` and `}}` for literal
+    /// braces.
+    fn from_str(template: &str) -> io::Result<Template> {
+        let error = |e: String| io::Error::other(format!("{}: {}", template, e));
+        let words = split_args(template, 1)
+            .map_err(|(_, kind)| error(kind.to_string()))?
+            .into_iter()
+            .map(|(_, word)| parse_word(&word).map_err(error))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        if words.is_empty() {
+            return Err(io::Error::other("launch template is empty"));
+        }
+        // with no arguments after `--`, there'd be no command to run
+        if let [Part::Field(Field::Args)] = words[0].as_slice() {
+            return Err(error("the command can't be {args}".into()));
+        }
+        Ok(Template { words })
+    }
+}
+
+/// Split a single word into text and placeholders.
+fn parse_word(word: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("missing closing }".into()),
+                    }
+                }
+                let field = match name.as_ref() {
+                    "alias" => Field::Alias,
+                    "hostname" => Field::Hostname,
+                    "user" => Field::User,
+                    "port" => Field::Port,
+                    "args" => Field::Args,
+                    _ => return Err(format!("unknown placeholder {{{}}}", name)),
+                };
+                if !text.is_empty() {
+                    parts.push(Part::Text(text.split_off(0)));
+                }
+                parts.push(Part::Field(field));
+            }
+            '}' => return Err("unexpected }".into()),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+impl Template {
+    /// The command line to run for this host. Extra arguments fill
+    /// `{args}`, or go on the end if the template doesn't use it.
+    pub fn argv(&self, host: &HostEntry, args: &[String]) -> Vec<String> {
+        let mut argv = vec![];
+        let mut used_args = false;
+
+        for word in &self.words {
+            // a bare {args} becomes one word per argument
+            if let [Part::Field(Field::Args)] = word.as_slice() {
+                argv.extend(args.iter().cloned());
+                used_args = true;
+                continue;
+            }
+
+            let mut out = String::new();
+            for part in word {
+                match part {
+                    Part::Text(text) => out.push_str(text),
+                    Part::Field(Field::Alias) => out.push_str(&host.name),
+                    Part::Field(Field::Hostname) => out.push_str(host.hostname()),
+                    Part::Field(Field::User) => out.push_str(&host.remote_user()),
+                    Part::Field(Field::Port) => out.push_str(&host.port.unwrap_or(22).to_string()),
+                    Part::Field(Field::Args) => {
+                        out.push_str(&args.join(" "));
+                        used_args = true;
+                    }
+                }
+            }
+            argv.push(out);
+        }
+
+        if !used_args {
+            argv.extend(args.iter().cloned());
+        }
+        argv
+    }
+
+    /// A `Command` ready to run for this host.
+    pub fn command(&self, host: &HostEntry, args: &[String]) -> io::Result<Command> {
+        let argv = self.argv(host, args);
+        let (program, rest) = argv
+            .split_first()
+            .ok_or_else(|| io::Error::other("launch template has no command"))?;
+        let mut cmd = Command::new(program);
+        cmd.args(rest);
+        Ok(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host() -> HostEntry {
+        let mut host = HostEntry::new("web");
+        host.hostname = Some("web.example.com".into());
+        host.user = Some("deploy".into());
+        host.port = Some(2222);
+        host
+    }
+
+    fn argv(template: &str, args: &[&str]) -> Vec<String> {
+        let args = args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        template.parse::<Template>().unwrap().argv(&host(), &args)
+    }
+
+    #[test]
+    fn test_templates() {
+        assert_eq!(vec!["ssh", "web"], argv(DEFAULT_TEMPLATE, &[]));
+        assert_eq!(
+            vec!["ssh", "web", "-L", "8080:localhost:80"],
+            argv(DEFAULT_TEMPLATE, &["-L", "8080:localhost:80"])
+        );
+        assert_eq!(
+            vec!["et", "deploy@web.example.com:2222"],
+            argv("et {user}@{hostname}:{port}", &[])
+        );
+        assert_eq!(
+            vec!["mosh", "web", "--", "htop"],
+            argv("mosh {alias} --", &["htop"])
+        );
+        assert_eq!(
+            vec!["run", "web", "--cmd=htop -d 5"],
+            argv("run {alias} --cmd={args}", &["htop", "-d", "5"])
+        );
+        assert_eq!(vec!["echo", "{web}"], argv("echo {{{alias}}}", &[]));
+        assert_eq!(
+            vec!["sh", "-c", "ssh web htop"],
+            argv("sh -c 'ssh {alias} {args}'", &["htop"])
+        );
+        assert_eq!(
+            vec!["/opt/My Tools/connect", "web"],
+            argv("\"/opt/My Tools/connect\" {alias}", &[])
+        );
+    }
+
+    #[test]
+    fn test_bad_templates() {
+        assert!("".parse::<Template>().is_err());
+        assert!("ssh {host}".parse::<Template>().is_err());
+        assert!("ssh {alias".parse::<Template>().is_err());
+        assert!("ssh alias}".parse::<Template>().is_err());
+        assert!("sh -c 'ssh {alias}".parse::<Template>().is_err());
+        assert!("{args}".parse::<Template>().is_err());
+        assert!("{args} {alias}".parse::<Template>().is_err());
+        assert!("{args}ssh {alias}".parse::<Template>().is_ok());
+    }
+
+    #[test]
+    fn test_actions() {
+        let launcher = Launcher::default();
+        let argv = |action: Action| {
+            action
+                .command(&launcher, &host(), &["-v".to_string()])
+                .unwrap()
+                .map(|cmd| {
+                    let mut argv = vec![cmd.get_program().to_string_lossy().to_string()];
+                    argv.extend(cmd.get_args().map(|a| a.to_string_lossy().to_string()));
+                    argv
+                })
+        };
+
+        assert_eq!(
+            Some(vec!["ssh".into(), "web".into(), "-v".into()]),
+            argv(Action::Ssh)
+        );
+        assert_eq!(
+            Some(vec!["scp".into(), "notes.txt".into(), "web:".into()]),
+            argv(Action::Scp("notes.txt".into()))
+        );
+        assert_eq!(
+            Some(vec![
+                "ssh".into(),
+                "-N".into(),
+                "-L".into(),
+                "8080:localhost:80".into(),
+                "web".into()
+            ]),
+            argv(Action::Forward("8080:localhost:80".into()))
+        );
+        assert_eq!(
+            Some(vec!["ssh".into(), "web".into(), "uptime -p".into()]),
+            argv(Action::Command("uptime -p".into()))
+        );
+        assert_eq!(None, argv(Action::CopyHostname));
+
+        assert_eq!(Some(Action::Mosh), Action::from_key('m'));
+        assert_eq!(
+            Action::Command("ls".into()),
+            Action::from_key('x').unwrap().with_input("ls")
+        );
+        assert!(Action::Sftp.prompt().is_none());
+    }
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+        assert_eq!("\x1b]52;c;d2ViLmxhbg==\x07", osc52("web.lan"));
+    }
+
+    #[test]
+    fn test_launcher() {
+        let launcher = Launcher {
+            default: Template::default(),
+            rules: vec![
+                (
+                    Pattern::list("*.lan !nas.lan"),
+                    "mosh {alias}".parse().unwrap(),
+                ),
+                (
+                    Pattern::list("*"),
+                    "kitty +kitten ssh {alias}".parse().unwrap(),
+                ),
+            ],
+        };
+        assert_eq!(
+            vec!["mosh", "pi.lan"],
+            launcher
+                .template("pi.lan")
+                .argv(&HostEntry::new("pi.lan"), &[])
+        );
+        assert_eq!("kitty", launcher.template("nas.lan").argv(&host(), &[])[0]);
+        assert_eq!(Template::default(), *Launcher::default().template("web"));
+    }
+}
@@ -1,6 +1,15 @@
 #[macro_use]
 pub mod color;
+pub mod history;
+pub mod keymap;
+pub mod launch;
+pub mod list;
+pub mod preview;
+pub mod query;
+pub mod search;
+pub mod settings;
 pub mod ssh_config;
+pub mod theme;
 pub mod tui;
 
 pub use tui::TUI as App;
@@ -0,0 +1,198 @@
+//! Print hosts without starting the TUI, for `shy list`.
+
+use {
+    crate::ssh_config::HostEntry,
+    std::{fmt::Write as _, io, str::FromStr},
+};
+
+/// How `shy list` prints hosts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Aligned columns, for people.
+    Plain,
+    /// Tab-separated values, for `cut` and friends.
+    Tsv,
+    /// A JSON array of objects.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Format> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "tsv" => Ok(Format::Tsv),
+            "json" => Ok(Format::Json),
+            _ => Err(io::Error::other(format!(
+                "unknown format: {} (try plain, tsv, or json)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Print hosts in the given format: alias, hostname, user, port, and
+/// the file the host came from.
+pub fn write<W: io::Write>(out: &mut W, hosts: &[&HostEntry], format: Format) -> io::Result<()> {
+    match format {
+        Format::Plain => write_plain(out, hosts),
+        Format::Tsv => write_tsv(out, hosts),
+        Format::Json => writeln!(out, "{}", to_json(hosts)),
+    }
+}
+
+/// The columns we print for each host. Missing values are empty.
+fn columns(host: &HostEntry) -> [String; 5] {
+    [
+        host.name.clone(),
+        host.hostname().to_string(),
+        host.user.clone().unwrap_or_default(),
+        host.port.map(|p| p.to_string()).unwrap_or_default(),
+        source_path(host),
+    ]
+}
+
+/// The file a host came from, if any.
+fn source_path(host: &HostEntry) -> String {
+    host.source
+        .as_ref()
+        .and_then(|s| s.path.as_ref())
+        .map(|p| p.display().to_string())
+        .unwrap_or_default()
+}
+
+fn write_plain<W: io::Write>(out: &mut W, hosts: &[&HostEntry]) -> io::Result<()> {
+    let rows = hosts.iter().map(|h| columns(h)).collect::<Vec<_>>();
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, col) in widths.iter_mut().zip(row) {
+            *width = (*width).max(col.chars().count());
+        }
+    }
+
+    for row in &rows {
+        let mut line = String::new();
+        for (width, col) in widths.iter().zip(row) {
+            let _ = write!(line, "{:width$}  ", col, width = width);
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+fn write_tsv<W: io::Write>(out: &mut W, hosts: &[&HostEntry]) -> io::Result<()> {
+    for host in hosts {
+        // tabs and newlines would break the format, so flatten them
+        let row = columns(host)
+            .iter()
+            .map(|col| col.replace(['\t', '\n'], " "))
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", row.join("\t"))?;
+    }
+    Ok(())
+}
+
+/// Hosts as a JSON array. Unset users, ports, groups and descriptions
+/// are `null`.
+fn to_json(hosts: &[&HostEntry]) -> String {
+    let mut out = String::from("[");
+    for (i, host) in hosts.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let source = host.source.clone().unwrap_or_default();
+        let _ = write!(
+            out,
+            "\n  {{\"alias\": {}, \"hostname\": {}, \"user\": {}, \"port\": {}, \"file\": {}, \"line\": {}, \"tags\": [{}], \"group\": {}, \"desc\": {}}}",
+            json_string(&host.name),
+            json_string(host.hostname()),
+            host.user.as_deref().map(json_string).unwrap_or_else(|| "null".into()),
+            host.port.map(|p| p.to_string()).unwrap_or_else(|| "null".into()),
+            source
+                .path
+                .map(|p| json_string(&p.display().to_string()))
+                .unwrap_or_else(|| "null".into()),
+            source.line,
+            host.tags
+                .iter()
+                .map(|t| json_string(t))
+                .collect::<Vec<_>>()
+                .join(", "),
+            host.group.as_deref().map(json_string).unwrap_or_else(|| "null".into()),
+            host.desc.as_deref().map(json_string).unwrap_or_else(|| "null".into()),
+        );
+    }
+    if !hosts.is_empty() {
+        out.push('\n');
+    }
+    out.push(']');
+    out
+}
+
+/// Quote and escape a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ssh_config::parse_ssh_config};
+
+    fn output(format: Format) -> String {
+        let hosts = parse_ssh_config(
+            "# shy: tags=app,eu group=Web\n\
+             Host web\n  HostName web.lan\n  User deploy\n  Port 2222\nHost \"q\\\"t\"\n",
+        )
+        .expect("failed to parse config");
+        let hosts = hosts.values().collect::<Vec<_>>();
+        let mut out = vec![];
+        write(&mut out, &hosts, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_plain() {
+        assert_eq!(
+            "web  web.lan  deploy  2222\nq\"t  q\"t\n",
+            output(Format::Plain)
+        );
+    }
+
+    #[test]
+    fn test_tsv() {
+        assert_eq!(
+            "web\tweb.lan\tdeploy\t2222\t\nq\"t\tq\"t\t\t\t\n",
+            output(Format::Tsv)
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            "[\n  {\"alias\": \"web\", \"hostname\": \"web.lan\", \"user\": \"deploy\", \"port\": 2222, \"file\": null, \"line\": 2, \"tags\": [\"app\", \"eu\"], \"group\": \"Web\", \"desc\": null},\n  {\"alias\": \"q\\\"t\", \"hostname\": \"q\\\"t\", \"user\": null, \"port\": null, \"file\": null, \"line\": 6, \"tags\": [], \"group\": null, \"desc\": null}\n]\n",
+            output(Format::Json)
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::Json, "json".parse().unwrap());
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
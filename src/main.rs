@@ -1,63 +1,286 @@
 use {
-    shy::App,
-    std::{io, os::unix::process::CommandExt, panic, process::Command},
+    fuzzy_matcher::skim::SkimMatcherV2,
+    shy::{
+        history::{self, Frecency, History},
+        launch, list, search,
+        settings::Settings,
+        ssh_config, App,
+    },
+    std::{
+        io::{self, Write},
+        os::unix::process::CommandExt,
+        panic,
+        path::PathBuf,
+    },
 };
 
 fn main() -> io::Result<()> {
-    let mut config_path = "~/.ssh/config";
-    let mut search_mode = false;
+    let mut list_mode = false;
+    let mut format = list::Format::Plain;
+    let mut filter = vec![];
 
-    let args = parse_args()?;
-    let mut args = args.iter();
+    let (args, ssh_args) = parse_args()?;
+    let mut settings = load_settings(&args);
+    // --exec-for rules are tried before the ones in the settings file
+    let mut exec_for = 0;
+    // `list` is only the subcommand up front, so it can be a filter too
+    let mut args = args.iter().peekable();
+    if args.peek().map(|arg| arg.as_str()) == Some("list") {
+        list_mode = true;
+        args.next();
+    }
     while let Some(arg) = args.next() {
         match arg.as_ref() {
             "-h" | "-help" | "--help" => return print_usage(),
             "-v" | "-version" | "--version" => return print_version(),
-            "-s" | "-search" | "--search" => search_mode = true,
+            "-print-keymap" | "--print-keymap" => {
+                print!("{}", settings.keymap);
+                return Ok(());
+            }
+            "-s" | "-search" | "--search" => settings.search = true,
+            "-match-exec" | "--match-exec" => settings.options.match_exec = true,
+            "-strict" | "--strict" => settings.options.lenient = false,
+            "-l" | "-list" | "--list" => list_mode = true,
+            "-no-history" | "--no-history" => settings.history = false,
+            "-clear-history" | "--clear-history" => return load_history().clear(),
+            "-prune-history" | "--prune-history" => {
+                let days = args
+                    .next()
+                    .and_then(|days| days.parse().ok())
+                    .ok_or_else(|| io::Error::other("Please provide a number of days."))?;
+                let removed =
+                    load_history().prune(history::now().saturating_sub(history::days(days)))?;
+                println!("Removed {} launches older than {} days.", removed, days);
+                return Ok(());
+            }
+            "-sort" | "--sort" => {
+                if let Some(name) = args.next() {
+                    settings.sort = name.parse()?;
+                } else {
+                    return Err(io::Error::other("Please provide a sort."));
+                }
+            }
+            "-view" | "--view" => {
+                if let Some(name) = args.next() {
+                    settings.view = name.parse()?;
+                } else {
+                    return Err(io::Error::other("Please provide a view."));
+                }
+            }
+            "-color" | "--color" => {
+                if let Some(choice) = args.next() {
+                    settings.color = choice.parse()?;
+                } else {
+                    return Err(io::Error::other("Please provide auto, always, or never."));
+                }
+            }
+            "-theme" | "--theme" => {
+                if let Some(name) = args.next() {
+                    settings.theme = name.parse().map_err(io::Error::other)?;
+                } else {
+                    return Err(io::Error::other("Please provide a theme."));
+                }
+            }
+            "-json" | "--json" => format = list::Format::Json,
+            "-tsv" | "--tsv" => format = list::Format::Tsv,
+            "-e" | "-exec" | "--exec" => {
+                if let Some(template) = args.next() {
+                    settings.launcher.default = template.parse()?;
+                    // the file's [launch.hosts] rules would win over it
+                    settings.launcher.rules.truncate(exec_for);
+                } else {
+                    return Err(io::Error::other("Please provide a launch template."));
+                }
+            }
+            "-exec-for" | "--exec-for" => {
+                if let (Some(patterns), Some(template)) = (args.next(), args.next()) {
+                    let patterns = ssh_config::Pattern::list(patterns);
+                    let rule = (patterns, template.parse()?);
+                    settings.launcher.rules.insert(exec_for, rule);
+                    exec_for += 1;
+                } else {
+                    return Err(io::Error::other(
+                        "Please provide a host pattern and a launch template.",
+                    ));
+                }
+            }
+            "-format" | "--format" => {
+                if let Some(name) = args.next() {
+                    format = name.parse()?;
+                } else {
+                    return Err(io::Error::other("Please provide a format."));
+                }
+            }
             "-c" | "-config" | "--config" | "-F" => {
                 if let Some(path) = args.next() {
-                    config_path = path;
+                    settings.config = path.clone();
                 } else {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Please provide a config path.",
-                    ));
+                    return Err(io::Error::other("Please provide a config path."));
                 }
             }
+            // already loaded by load_settings()
+            "-settings" | "--settings" => {
+                args.next();
+            }
+            arg if !arg.starts_with('-') => filter.push(arg),
             _ => {}
         }
     }
 
-    match run(config_path, search_mode) {
+    if !list_mode && !filter.is_empty() {
+        return Err(io::Error::other(format!(
+            "Unexpected argument: {}. Filters only work with `shy list`.",
+            filter[0]
+        )));
+    }
+
+    let mut history = load_history();
+    let frecency = history.frecency(history::now());
+
+    if list_mode {
+        return print_list(&settings, format, &filter.join(" "), &frecency);
+    }
+
+    match run(&settings, &ssh_args, frecency) {
         Ok(None) => {}
         Err(e) => {
-            if matches!(e.kind(), io::ErrorKind::NotFound) {
-                eprintln!("error: ~/.ssh/config not found");
+            if let Some(err) = e.get_ref().and_then(|e| e.downcast_ref()) {
+                print_parse_error(err);
+            } else if matches!(e.kind(), io::ErrorKind::NotFound) {
+                eprintln!("error: {} not found", settings.config);
             } else {
                 eprintln!("{}", e);
             }
             std::process::exit(1);
         }
-        Ok(Some(hostname)) => {
+        Ok(Some((host, action))) => {
             std::env::set_var("TERM", "xterm"); // TODO xterm-kitty hack
-            let mut cmd = Command::new("ssh");
-            let cmd = cmd.arg(hostname);
-            let err = cmd.exec();
-            eprintln!("{:?}", err);
+            if settings.history {
+                if let Err(e) = history.record(&host.name, action.name(), history::now()) {
+                    eprintln!("warning: can't save history: {}", e);
+                }
+            }
+            match action.command(&settings.launcher, &host, &ssh_args) {
+                Ok(Some(mut cmd)) => {
+                    let err = cmd.exec();
+                    eprintln!("{:?}", err);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-/// Run the app, optionally returning a host to SSH to.
-fn run(config_path: &str, search_mode: bool) -> io::Result<Option<String>> {
+/// Run the app, optionally returning a host and what to do with it.
+fn run(
+    settings: &Settings,
+    ssh_args: &[String],
+    frecency: Frecency,
+) -> io::Result<Option<(ssh_config::HostEntry, launch::Action)>> {
     setup_panic_hook();
-    let mut app = App::new(config_path)?;
-    if search_mode {
-        app.mode = shy::tui::Mode::Search;
+    let options = &settings.options;
+    let mut config = ssh_config::Config::load_with(&settings.config, options)?;
+    let warnings = std::mem::take(&mut config.warnings);
+    let mut app = App::new(config.hosts_with(options), warnings, settings)?;
+    app.ssh_args = ssh_args.to_vec();
+    app.frecency = frecency;
+    app.sort_by(settings.sort);
+    let launch = app.run()?;
+    // the terminal is put back as the app drops, so make sure that's
+    // written before we exec ssh
+    drop(app);
+    io::stdout().flush()?;
+    Ok(launch.map(|launch| (config.resolve_with(&launch.alias, options), launch.action)))
+}
+
+/// `shy list`: print matching hosts, best match first, then exit.
+fn print_list(
+    settings: &Settings,
+    format: list::Format,
+    filter: &str,
+    frecency: &Frecency,
+) -> io::Result<()> {
+    let config = match ssh_config::Config::load_with(&settings.config, &settings.options) {
+        Ok(config) => config,
+        Err(e) => {
+            if let Some(err) = e.get_ref().and_then(|e| e.downcast_ref()) {
+                print_parse_error(err);
+                std::process::exit(1);
+            }
+            return Err(e);
+        }
+    };
+    // keep stdout clean for scripts
+    for warning in &config.warnings {
+        eprintln!("warning: {}", warning);
     }
-    Ok(app.run()?)
+
+    let hosts = config.hosts_with(&settings.options);
+    let matches = search::rank(&SkimMatcherV2::default(), &hosts, filter, frecency);
+    list::write(&mut io::stdout().lock(), &matches, format)
+}
+
+/// shy's settings: `--settings FILE`, or the default file. Problems
+/// with it are warnings, so shy always starts.
+fn load_settings(args: &[String]) -> Settings {
+    let path = args
+        .iter()
+        .position(|arg| arg == "--settings" || arg == "-settings")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from)
+        .unwrap_or_else(Settings::default_path);
+    let settings = Settings::load(&path).unwrap_or_else(|e| {
+        eprintln!("warning: can't read {}: {}", path.display(), e);
+        Settings::default()
+    });
+    for warning in &settings.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    settings
+}
+
+/// The launch history. If it can't be read, start fresh rather than
+/// refusing to run.
+fn load_history() -> History {
+    let path = History::default_path();
+    History::load(path.clone()).unwrap_or_else(|e| {
+        eprintln!("warning: can't read {}: {}", path.display(), e);
+        History {
+            path,
+            visits: vec![],
+        }
+    })
+}
+
+/// Print a config error compiler-style, with the line and a caret
+/// pointing at the problem.
+fn print_parse_error(err: &ssh_config::ParseError) {
+    let path = err
+        .path
+        .as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "<config>".into());
+    let line = err.line.to_string();
+    let gutter = " ".repeat(line.len());
+    // keep tabs so the caret lines up with the text above it
+    let indent = err
+        .text
+        .chars()
+        .take(err.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+
+    eprintln!("error: {}", err.kind);
+    eprintln!("{}--> {}:{}:{}", gutter, path, err.line, err.column);
+    eprintln!("{} |", gutter);
+    eprintln!("{} | {}", line, err.text);
+    eprintln!("{} | {}^", gutter, indent);
 }
 
 /// We need to cleanup the terminal before exiting, even on panic!
@@ -67,31 +290,61 @@ fn setup_panic_hook() {
     }));
 }
 
-/// Converts -c=file into ["-c", "file"]
-fn parse_args() -> io::Result<Vec<String>> {
+/// Converts -c=file into ["-c", "file"]. Everything after `--` is
+/// returned separately, untouched, to pass on to ssh.
+fn parse_args() -> io::Result<(Vec<String>, Vec<String>)> {
     let mut args = vec![];
-    for arg in std::env::args().skip(1).collect::<Vec<String>>() {
-        if arg.starts_with('-') && arg.contains('=') {
-            for part in arg.split("=") {
+    let mut argv = std::env::args().skip(1);
+    for arg in argv.by_ref() {
+        if arg == "--" {
+            break;
+        } else if arg.starts_with('-') && arg.contains('=') {
+            for part in arg.splitn(2, '=') {
                 args.push(part.to_string());
             }
         } else {
             args.push(arg);
         }
     }
-    Ok(args)
+    Ok((args, argv.collect()))
 }
 
 /// --help
 fn print_usage() -> io::Result<()> {
     println!(
-        "Usage: shy [options]
+        "Usage: shy [options] [-- SSH_ARGS...]
+       shy list [options] [FILTER]
 
 Options:
     -c, --config FILE    Use FILE instead of ~/.ssh/config
+    --settings FILE      Read settings from FILE instead of
+                         ~/.config/shy/config.toml
     -s, --search         Start in Search mode.
+    --match-exec         Run commands in `Match exec` blocks.
+    --strict             Exit on any config error instead of skipping it.
+    -l, --list           Print hosts matching FILTER and exit.
+    --format FORMAT      List as plain (default), tsv, or json.
+    --json, --tsv        Shorthand for --format json / --format tsv.
+    --sort ORDER         List hosts in config (default) or frecency order.
+    --view VIEW          Group hosts by groups (default), files, or flat.
+    --theme THEME        Use the default, light, high-contrast, nord, or
+                         mono colors.
+    --color WHEN         Use colors auto (default), always, or never.
+    --no-history         Don't remember which hosts you launch.
+    --clear-history      Forget every launch and exit.
+    --prune-history DAYS Forget launches older than DAYS and exit.
+    -e, --exec TEMPLATE  Run TEMPLATE instead of `ssh {{alias}} {{args}}`.
+    --exec-for PATTERNS TEMPLATE
+                         Use TEMPLATE for hosts matching PATTERNS.
+    --print-keymap       Print every key binding, as settings, and exit.
     -v, --version        Print shy version and exit.
-    -h, --help           Show this message."
+    -h, --help           Show this message.
+
+Anything after -- is passed to ssh after the host you pick, like
+`shy -- -L 8080:localhost:80` or `shy -- htop`.
+
+Templates can use {{alias}}, {{hostname}}, {{user}}, {{port}}, and {{args}}:
+`shy --exec 'et {{user}}@{{hostname}}:{{port}}'`"
     );
     Ok(())
 }
@@ -0,0 +1,171 @@
+//! The details shown in the preview pane next to the host list.
+
+use {
+    crate::ssh_config::{HostEntry, HostMap},
+    std::collections::HashSet,
+};
+
+/// The options ssh will really use for `host`, as (label, value)
+/// lines. Options with more than one value get a line each, and only
+/// the first is labeled.
+pub fn lines(hosts: &HostMap, host: &HostEntry) -> Vec<(&'static str, String)> {
+    let mut lines = vec![
+        ("HostName", host.hostname().to_string()),
+        ("User", host.remote_user()),
+        ("Port", host.port.unwrap_or(22).to_string()),
+    ];
+    let mut add = |label, values: Vec<String>| {
+        for (i, value) in values.into_iter().enumerate() {
+            lines.push((if i == 0 { label } else { "" }, value));
+        }
+    };
+
+    add("Group", host.group.iter().cloned().collect());
+    if !host.tags.is_empty() {
+        add("Tags", vec![host.tags.join(", ")]);
+    }
+    add("IdentityFile", host.identity_files.clone());
+    let chain = jump_chain(hosts, host);
+    if !chain.is_empty() {
+        add("ProxyJump", vec![chain.join(" → ")]);
+    }
+    add("ProxyCommand", host.proxy_command.iter().cloned().collect());
+    add(
+        "LocalForward",
+        host.local_forwards
+            .iter()
+            .map(|f| match &f.target {
+                Some(target) => format!("{} → {}", f.listen, target),
+                None => f.listen.clone(),
+            })
+            .collect(),
+    );
+    add(
+        "RemoteForward",
+        host.remote_forwards
+            .iter()
+            .map(|f| match &f.target {
+                Some(target) => format!("{} ← {}", f.listen, target),
+                None => format!("{} (socks)", f.listen),
+            })
+            .collect(),
+    );
+    add(
+        "DynamicForward",
+        host.options
+            .get("dynamicforward")
+            .cloned()
+            .unwrap_or_default(),
+    );
+    if let Some(source) = &host.source {
+        let path = source
+            .path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "<config>".into());
+        add("Source", vec![format!("{}:{}", path, source.line)]);
+    }
+    lines
+}
+
+/// Every host ssh goes through to reach `host`, first hop first. A
+/// jump host with its own ProxyJump is followed back to the start,
+/// or to the last new hop if they go round in a circle.
+pub fn jump_chain(hosts: &HostMap, host: &HostEntry) -> Vec<String> {
+    // ssh reads the config again for each hop, `[user@]host[:port]`
+    let alias_of = |hop: &str| {
+        let alias = hop.rsplit('@').next().unwrap_or(hop);
+        alias.split(':').next().unwrap_or(alias).to_string()
+    };
+    let mut chain = vec![];
+    let mut seen = HashSet::new();
+    seen.insert(host.name.clone());
+    let mut current = host;
+    while let Some(jumps) = current.proxy_jump.as_deref() {
+        if jumps.eq_ignore_ascii_case("none") {
+            break;
+        }
+        let hops = jumps
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .collect::<Vec<_>>();
+        if hops.iter().any(|hop| seen.contains(&alias_of(hop))) {
+            break;
+        }
+        seen.extend(hops.iter().map(|hop| alias_of(hop)));
+        let first = alias_of(&hops[0]);
+        chain.splice(0..0, hops);
+        match hosts.get(&first) {
+            Some(hop) => current = hop,
+            None => break,
+        }
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ssh_config::parse_ssh_config};
+
+    #[test]
+    fn test_jump_chain() {
+        let hosts = parse_ssh_config(
+            "Host web\n  ProxyJump bastion,admin@inner:2222\n\
+             Host inner\n  ProxyJump nope\n\
+             Host bastion\n  ProxyJump edge\n\
+             Host edge\n  ProxyJump web\n\
+             Host direct\n  ProxyJump none\n\
+             Host loop\n  ProxyJump loop\n",
+        )
+        .expect("failed to parse config");
+
+        assert_eq!(
+            vec!["edge", "bastion", "admin@inner:2222"],
+            jump_chain(&hosts, &hosts["web"])
+        );
+        assert_eq!(
+            vec!["bastion", "admin@inner:2222", "web"],
+            jump_chain(&hosts, &hosts["edge"])
+        );
+        assert_eq!(vec!["nope"], jump_chain(&hosts, &hosts["inner"]));
+        assert!(jump_chain(&hosts, &hosts["direct"]).is_empty());
+        assert!(jump_chain(&hosts, &hosts["loop"]).is_empty());
+    }
+
+    #[test]
+    fn test_lines() {
+        let hosts = crate::ssh_config::load_ssh_config("./tests/test_config")
+            .expect("failed to parse config");
+        let lines = lines(&hosts, &hosts["docker1"]);
+        let get = |label| {
+            lines
+                .iter()
+                .find(|(l, _)| *l == label)
+                .map(|(_, v)| v.as_str())
+        };
+
+        assert_eq!(Some("docker1.mycloud.net"), get("HostName"));
+        assert_eq!(Some("22"), get("Port"));
+        assert_eq!(Some("~/.ssh/docker.key"), get("IdentityFile"));
+        assert_eq!(Some("gateway"), get("ProxyJump"));
+        assert_eq!(Some("8080 → localhost:80"), get("LocalForward"));
+        assert_eq!(Some("1080 (socks)"), get("RemoteForward"));
+        assert!(get("Source").unwrap().ends_with("tests/test_config:13"));
+    }
+
+    #[test]
+    fn test_dynamic_forwards() {
+        let hosts = parse_ssh_config(
+            "Host socks\n  DynamicForward 1080\n  DynamicForward localhost:1081\n",
+        )
+        .expect("failed to parse config");
+        let lines = lines(&hosts, &hosts["socks"]);
+        let at = lines
+            .iter()
+            .position(|(l, _)| *l == "DynamicForward")
+            .expect("no DynamicForward line");
+
+        assert_eq!(("DynamicForward", "1080".to_string()), lines[at]);
+        assert_eq!(("", "localhost:1081".to_string()), lines[at + 1]);
+    }
+}
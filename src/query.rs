@@ -0,0 +1,369 @@
+//! The search syntax, borrowed from fzf:
+//!
+//! - `web` fuzzy matches
+//! - `'web` matches exactly, anywhere
+//! - `^web` matches the start, `web$` the end, `^web$` the whole thing
+//! - `!web` matches hosts that don't contain `web`
+//! - `web db` matches hosts that match both
+//! - `web | db` matches hosts that match either
+//!
+//! Any term can start with a field prefix like `h:`, after the `!`.
+//! Lowercase terms ignore case.
+
+use {
+    crate::{
+        search::{Field, Match},
+        ssh_config::HostEntry,
+    },
+    fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
+};
+
+/// A parsed search: every group has to match, and a group matches
+/// when any of its terms do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub groups: Vec<Vec<Term>>,
+}
+
+/// A single word of the search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub text: String,
+    pub kind: Kind,
+    /// Only search this field, instead of all of them.
+    pub field: Option<Field>,
+    /// Match hosts that *don't* contain the text.
+    pub negated: bool,
+}
+
+/// How a term matches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Fuzzy,
+    /// `'text`
+    Exact,
+    /// `^text`
+    Prefix,
+    /// `text$`
+    Suffix,
+    /// `^text$`
+    Equal,
+}
+
+impl Query {
+    /// Parse a search. This never fails: anything that isn't an
+    /// operator is just text to look for.
+    pub fn parse(input: &str) -> Query {
+        let mut groups: Vec<Vec<Term>> = vec![];
+        let mut or = false;
+        for word in split_words(input) {
+            if word == "|" {
+                or = !groups.is_empty();
+                continue;
+            }
+            let term = match Term::parse(&word) {
+                Some(term) => term,
+                None => continue,
+            };
+            match groups.last_mut() {
+                Some(group) if or => group.push(term),
+                _ => groups.push(vec![term]),
+            }
+            or = false;
+        }
+        Query { groups }
+    }
+
+    /// Does this query match everything?
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// How a host matches, if it does. The score is the total of the
+    /// best term in each group.
+    pub fn find(&self, matcher: &SkimMatcherV2, host: &HostEntry) -> Option<Match> {
+        let mut found = Match {
+            score: 0,
+            fields: vec![],
+        };
+        for group in &self.groups {
+            // max_by_key picks the last of equal scores; keep the first
+            let best = group
+                .iter()
+                .rev()
+                .filter_map(|term| term.find(matcher, host))
+                .max_by_key(|m| m.score)?;
+            found.score += best.score;
+            for (field, indices) in best.fields {
+                found.add(field, indices);
+            }
+        }
+        Some(found)
+    }
+}
+
+impl Term {
+    /// Parse one word, like `!h:^10.0.`. Returns None if there's
+    /// nothing left to search for once the operators are removed.
+    pub fn parse(word: &str) -> Option<Term> {
+        let mut text = word;
+        let mut negated = false;
+        let mut field = None;
+
+        if let Some(rest) = text.strip_prefix('!') {
+            negated = true;
+            text = rest;
+        }
+        for f in &Field::ALL {
+            if let Some(rest) = text.strip_prefix(f.prefix()) {
+                field = Some(*f);
+                text = rest;
+                break;
+            }
+        }
+        if !negated {
+            if let Some(rest) = text.strip_prefix('!') {
+                negated = true;
+                text = rest;
+            }
+        }
+
+        let kind = if let Some(rest) = text.strip_prefix('\'') {
+            text = rest;
+            Kind::Exact
+        } else {
+            let prefix = text.strip_prefix('^');
+            text = prefix.unwrap_or(text);
+            // a lone "$" is text, not an operator
+            let suffix = text.strip_suffix('$').filter(|t| !t.is_empty());
+            text = suffix.unwrap_or(text);
+            match (prefix.is_some(), suffix.is_some()) {
+                (true, true) => Kind::Equal,
+                (true, false) => Kind::Prefix,
+                (false, true) => Kind::Suffix,
+                // like fzf, negated terms are always exact
+                (false, false) if negated => Kind::Exact,
+                (false, false) => Kind::Fuzzy,
+            }
+        };
+
+        if text.is_empty() {
+            return None;
+        }
+        Some(Term {
+            text: text.to_string(),
+            kind,
+            field,
+            negated,
+        })
+    }
+
+    /// The fields this term looks at.
+    fn fields(&self) -> Vec<Field> {
+        match self.field {
+            Some(field) => vec![field],
+            None => Field::ALL.to_vec(),
+        }
+    }
+
+    /// Does this term match the host? Unless it's negated, the match
+    /// says which field and chars matched. Ties go to the field that
+    /// comes first.
+    fn find(&self, matcher: &SkimMatcherV2, host: &HostEntry) -> Option<Match> {
+        let mut best: Option<(i64, Field, Vec<usize>)> = None;
+        for field in self.fields() {
+            let text = match field.text(host) {
+                Some(text) => text,
+                None => continue,
+            };
+            if let Some((score, indices)) = self.find_in(matcher, &text) {
+                if best.as_ref().is_none_or(|(best, _, _)| score > *best) {
+                    best = Some((score, field, indices));
+                }
+            }
+        }
+
+        match best {
+            _ if self.negated => best.is_none().then_some(Match {
+                score: 0,
+                fields: vec![],
+            }),
+            Some((score, field, indices)) => Some(Match {
+                score,
+                fields: vec![(field, indices)],
+            }),
+            None => None,
+        }
+    }
+
+    /// Match this term against some text, returning the score and the
+    /// chars that matched.
+    fn find_in(&self, matcher: &SkimMatcherV2, text: &str) -> Option<(i64, Vec<usize>)> {
+        if self.kind == Kind::Fuzzy {
+            return matcher.fuzzy_indices(text, &self.text);
+        }
+
+        // smart case, like the fuzzy matcher. Fold one char at a time
+        // so indices still line up with the original text.
+        let ignore_case = !self.text.chars().any(char::is_uppercase);
+        let fold = |c: char| {
+            if ignore_case {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                c
+            }
+        };
+        let hay = text.chars().map(fold).collect::<Vec<_>>();
+        let needle = self.text.chars().map(fold).collect::<Vec<_>>();
+        if needle.len() > hay.len() {
+            return None;
+        }
+
+        let at = |i: usize| hay[i..].starts_with(&needle);
+        let start = match self.kind {
+            Kind::Prefix => Some(0).filter(|_| at(0)),
+            Kind::Suffix => Some(hay.len() - needle.len()).filter(|&i| at(i)),
+            Kind::Equal => Some(0).filter(|_| hay == needle),
+            _ => (0..=hay.len() - needle.len()).find(|&i| at(i)),
+        }?;
+
+        // exact matches still get a fuzzy score, so they rank sensibly
+        let score = matcher.fuzzy_match(text, &self.text).unwrap_or(0);
+        Some((score, (start..start + needle.len()).collect()))
+    }
+}
+
+/// Split a search into words on spaces. A backslash before a space
+/// keeps it in the word.
+fn split_words(input: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => word.push(chars.next().unwrap()),
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str, kind: Kind) -> Term {
+        Term {
+            text: text.into(),
+            kind,
+            field: None,
+            negated: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_terms() {
+        assert_eq!(Some(term("web", Kind::Fuzzy)), Term::parse("web"));
+        assert_eq!(Some(term("web", Kind::Exact)), Term::parse("'web"));
+        assert_eq!(Some(term("web", Kind::Prefix)), Term::parse("^web"));
+        assert_eq!(Some(term("web", Kind::Suffix)), Term::parse("web$"));
+        assert_eq!(Some(term("web", Kind::Equal)), Term::parse("^web$"));
+        assert_eq!(Some(term("$", Kind::Fuzzy)), Term::parse("$"));
+        assert_eq!(None, Term::parse("^"));
+        assert_eq!(None, Term::parse("!"));
+
+        let negated = Term {
+            negated: true,
+            ..term("stg", Kind::Exact)
+        };
+        assert_eq!(Some(negated.clone()), Term::parse("!stg"));
+        assert_eq!(
+            Some(Term {
+                kind: Kind::Prefix,
+                ..negated
+            }),
+            Term::parse("!^stg")
+        );
+
+        let field = Term {
+            field: Some(Field::Hostname),
+            ..term("10.0.", Kind::Prefix)
+        };
+        assert_eq!(Some(field.clone()), Term::parse("h:^10.0."));
+        let field = Term {
+            negated: true,
+            ..field
+        };
+        assert_eq!(Some(field.clone()), Term::parse("!h:^10.0."));
+        assert_eq!(Some(field), Term::parse("h:!^10.0."));
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert!(Query::parse("").is_empty());
+        assert!(Query::parse("  | ").is_empty());
+
+        let query = Query::parse("^prod db | cache$  !stg");
+        let kinds = query
+            .groups
+            .iter()
+            .map(|g| g.iter().map(|t| (t.text.as_ref(), t.kind)).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            vec![
+                vec![("prod", Kind::Prefix)],
+                vec![("db", Kind::Fuzzy), ("cache", Kind::Suffix)],
+                vec![("stg", Kind::Exact)],
+            ],
+            kinds
+        );
+
+        let query = Query::parse("my\\ host");
+        assert_eq!("my host", query.groups[0][0].text);
+    }
+
+    #[test]
+    fn test_matching() {
+        let matcher = SkimMatcherV2::default();
+        let mut host = HostEntry::new("prod-db-01");
+        host.hostname = Some("10.0.3.7".into());
+        host.user = Some("Postgres".into());
+        let matches = |query| Query::parse(query).find(&matcher, &host).is_some();
+
+        assert!(matches(""));
+        assert!(matches("pdb"));
+        assert!(!matches("'pdb"));
+        assert!(matches("'db-0"));
+        assert!(matches("^prod"));
+        assert!(!matches("^db"));
+        assert!(matches("01$"));
+        assert!(matches("^prod-db-01$"));
+        assert!(!matches("^prod$"));
+        assert!(matches("!qa"));
+        assert!(!matches("!prod"));
+        assert!(!matches("!h:^10."));
+        assert!(matches("!u:^10."));
+        assert!(matches("prod db"));
+        assert!(!matches("prod qa"));
+        assert!(matches("qa | prod"));
+        assert!(matches("qa | prod db"));
+        assert!(!matches("qa | stage db"));
+        // smart case
+        assert!(matches("u:'postgres"));
+        assert!(matches("u:'Postgres"));
+        assert!(!matches("u:'POSTGRES"));
+
+        let found = Query::parse("^prod 01$").find(&matcher, &host).unwrap();
+        assert_eq!(vec![(Field::Alias, vec![0, 1, 2, 3, 8, 9])], found.fields);
+        let found = Query::parse("'0.3").find(&matcher, &host).unwrap();
+        assert_eq!(vec![(Field::Hostname, vec![3, 4, 5])], found.fields);
+    }
+}
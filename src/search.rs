@@ -0,0 +1,229 @@
+//! Fuzzy finding hosts. The TUI and `shy list` both use this, so
+//! they always agree on what matches and in what order.
+
+use {
+    crate::{
+        history::Frecency,
+        query::Query,
+        ssh_config::{HostEntry, HostMap},
+    },
+    fuzzy_matcher::skim::SkimMatcherV2,
+    std::{borrow::Cow, cmp::Reverse},
+};
+
+/// Parts of a host we search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Alias,
+    Hostname,
+    User,
+    /// Tags, group, description and comments from the config.
+    Tags,
+}
+
+impl Field {
+    /// Every field, in the order we prefer them when they score the
+    /// same.
+    pub const ALL: [Field; 4] = [Field::Alias, Field::Hostname, Field::User, Field::Tags];
+
+    /// The prefix that limits a search to this field, like `h:`.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Field::Alias => "a:",
+            Field::Hostname => "h:",
+            Field::User => "u:",
+            Field::Tags => "t:",
+        }
+    }
+
+    /// This field's text for a host, if it has any.
+    pub fn text(self, host: &HostEntry) -> Option<Cow<'_, str>> {
+        match self {
+            Field::Alias => Some(Cow::from(&host.name)),
+            Field::Hostname => host.hostname.as_deref().map(Cow::from),
+            Field::User => host.user.as_deref().map(Cow::from),
+            Field::Tags => {
+                let words = host
+                    .tags
+                    .iter()
+                    .chain(&host.group)
+                    .chain(&host.desc)
+                    .chain(&host.comments)
+                    .map(|s| s.as_str())
+                    .collect::<Vec<_>>();
+                Some(words.join(" "))
+                    .filter(|s| !s.is_empty())
+                    .map(Cow::from)
+            }
+        }
+    }
+}
+
+/// How a host matched the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub score: i64,
+    /// Each field that matched and which of its characters did,
+    /// counted in chars, not bytes. In `Field::ALL` order.
+    pub fields: Vec<(Field, Vec<usize>)>,
+}
+
+impl Match {
+    /// Record that some chars of a field matched.
+    pub fn add(&mut self, field: Field, indices: Vec<usize>) {
+        match self.fields.iter_mut().find(|(f, _)| *f == field) {
+            Some((_, existing)) => {
+                existing.extend(indices);
+                existing.sort_unstable();
+                existing.dedup();
+            }
+            None => {
+                self.fields.push((field, indices));
+                let order = |f: &Field| Field::ALL.iter().position(|a| a == f);
+                self.fields.sort_by_key(|(f, _)| order(f));
+            }
+        }
+    }
+
+    /// The chars of a field that matched, if any did.
+    pub fn indices(&self, field: Field) -> Option<&[usize]> {
+        self.fields
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, indices)| indices.as_ref())
+    }
+}
+
+/// Every host that matches `query`, best match first. Ties go to the
+/// host with the higher frecency, then config order.
+pub fn rank<'a>(
+    matcher: &SkimMatcherV2,
+    hosts: &'a HostMap,
+    query: &str,
+    frecency: &Frecency,
+) -> Vec<&'a HostEntry> {
+    matches(matcher, hosts, query, frecency)
+        .into_iter()
+        .filter_map(|i| hosts.get_index(i).map(|(_, host)| host))
+        .collect()
+}
+
+/// Like `rank`, but returns each host's index in `hosts`.
+pub fn matches(
+    matcher: &SkimMatcherV2,
+    hosts: &HostMap,
+    query: &str,
+    frecency: &Frecency,
+) -> Vec<usize> {
+    let query = Query::parse(query);
+    let mut matches = hosts
+        .values()
+        .enumerate()
+        .filter_map(|(i, host)| query.find(matcher, host).map(|m| (m.score, i, host)))
+        .collect::<Vec<_>>();
+    // sort_by_key is stable, which keeps ties in config order
+    matches.sort_by_key(|(score, _, host)| {
+        (
+            Reverse(*score),
+            Reverse(frecency.get(&host.name).copied().unwrap_or(0)),
+        )
+    });
+    matches.into_iter().map(|(_, i, _)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ssh_config::load_ssh_config};
+
+    #[test]
+    fn test_rank() {
+        let hosts = load_ssh_config("./tests/test_config").expect("failed to parse config");
+        let matcher = SkimMatcherV2::default();
+        let mut frecency = Frecency::new();
+
+        let names = |query, frecency: &Frecency| {
+            rank(&matcher, &hosts, query, frecency)
+                .iter()
+                .map(|h| h.name.as_ref())
+                .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(
+            vec!["docker1", "docker2", "docker3"],
+            names("docker", &frecency)
+        );
+        assert_eq!("docker3", names("dkr3", &frecency)[0]);
+        assert_eq!("nas01", names("nas", &frecency)[0]);
+        assert!(names("zzz", &frecency).is_empty());
+        assert_eq!(hosts.len(), names("", &frecency).len());
+
+        frecency.insert("docker3".into(), 100);
+        frecency.insert("docker2".into(), 50);
+        assert_eq!(
+            vec!["docker3", "docker2", "docker1"],
+            names("docker", &frecency)
+        );
+        assert_eq!("docker3", names("", &frecency)[0]);
+    }
+
+    #[test]
+    fn test_find() {
+        let matcher = SkimMatcherV2::default();
+        let indices = |alias, query| {
+            Query::parse(query)
+                .find(&matcher, &HostEntry::new(alias))
+                .map(|m| m.indices(Field::Alias).unwrap_or_default().to_vec())
+        };
+
+        assert_eq!(Some(vec![0, 6]), indices("docker1", "d1"));
+        // the best "ve", not the first "v" and the "e" after it
+        assert_eq!(Some(vec![6, 7]), indices("devserver", "ve"));
+        // lowercase queries ignore case
+        assert_eq!(Some(vec![0, 1]), indices("NAS01", "na"));
+        assert_eq!(Some(vec![]), indices("nas01", ""));
+        assert_eq!(None, indices("nas01", "x"));
+        // every term's matches are highlighted
+        assert_eq!(Some(vec![0, 1, 2, 3, 4]), indices("nas01", "^nas 01$"));
+    }
+
+    #[test]
+    fn test_fields() {
+        let hosts = load_ssh_config("./tests/test_config").expect("failed to parse config");
+        let matcher = SkimMatcherV2::default();
+        let fields = |alias: &str, query| {
+            Query::parse(query)
+                .find(&matcher, &hosts[alias])
+                .map(|m| m.fields.iter().map(|(f, _)| *f).collect::<Vec<_>>())
+        };
+
+        let m = Query::parse("192.168")
+            .find(&matcher, &hosts["nas01"])
+            .unwrap();
+        assert_eq!(vec![(Field::Hostname, vec![0, 1, 2, 3, 4, 5, 6])], m.fields);
+
+        assert_eq!(Some(vec![Field::Alias]), fields("nas01", "nas"));
+        assert_eq!(Some(vec![Field::User]), fields("nas01", "u:root"));
+        assert_eq!(
+            Some(vec![Field::Alias, Field::User]),
+            fields("nas01", "root nas")
+        );
+        assert_eq!(None, fields("nas01", "a:root"));
+        assert_eq!(None, fields("nas01", "h:nas"));
+
+        let mut host = HostEntry::new("db1");
+        host.comments = vec!["primary postgres".into()];
+        let m = Query::parse("t:postgres").find(&matcher, &host).unwrap();
+        assert_eq!(
+            vec![(Field::Tags, vec![8, 9, 10, 11, 12, 13, 14, 15])],
+            m.fields
+        );
+
+        host.tags = vec!["prod".into()];
+        host.group = Some("EU".into());
+        assert_eq!(
+            Some("prod EU primary postgres"),
+            Field::Tags.text(&host).as_deref()
+        );
+        assert!(Query::parse("t:^prod").find(&matcher, &host).is_some());
+    }
+}
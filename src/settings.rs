@@ -0,0 +1,469 @@
+//! shy's own settings, from `~/.config/shy/config.toml`:
+//!
+//! ```toml
+//! config = "~/.ssh/config"
+//! sort = "frecency"
+//! preview = true
+//! jump = 10
+//! theme = "light"
+//!
+//! [launch]
+//! template = "ssh {alias} {args}"
+//!
+//! [launch.hosts]
+//! "*.lan" = "et {user}@{hostname}:{port}"
+//!
+//! [keys.nav]
+//! G = "bottom"
+//!
+//! [colors]
+//! selection = "bold #ff8700"
+//! ```
+//!
+//! Only the bits of TOML we need are understood: `[sections]`, and
+//! `key = value` pairs where the value is a string, integer, or
+//! boolean. Anything we don't understand is a warning, not an error,
+//! so a typo never keeps shy from starting.
+
+use {
+    crate::{
+        color::ColorChoice,
+        keymap::Keymap,
+        launch::Launcher,
+        ssh_config::{Options, Pattern},
+        theme::Theme,
+        tui::{Sort, View},
+    },
+    std::{
+        env, fs, io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Everything that can be set in the settings file. Command line
+/// flags are applied on top.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// The ssh config to read.
+    pub config: String,
+    /// Start in Search mode.
+    pub search: bool,
+    pub sort: Sort,
+    pub view: View,
+    /// Show the preview pane.
+    pub preview: bool,
+    /// How many rows PageUp and PageDown move.
+    pub jump: usize,
+    /// Record launches in the history file.
+    pub history: bool,
+    /// How to read the ssh config.
+    pub options: Options,
+    pub launcher: Launcher,
+    pub keymap: Keymap,
+    /// The built-in theme to start from.
+    pub theme: Theme,
+    /// Styles from the `[colors]` section, as `(slot, style)`. They're
+    /// kept apart from `theme` so they still apply after `--theme`.
+    pub colors: Vec<(String, String)>,
+    /// Whether to use the theme's colors.
+    pub color: ColorChoice,
+    /// Settings we skipped, as `file:line: problem`.
+    pub warnings: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            config: "~/.ssh/config".into(),
+            search: false,
+            sort: Sort::Config,
+            view: View::Groups,
+            preview: false,
+            jump: 5,
+            history: true,
+            options: Options {
+                lenient: true,
+                ..Default::default()
+            },
+            launcher: Launcher::default(),
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            colors: vec![],
+            color: ColorChoice::Auto,
+            warnings: vec![],
+        }
+    }
+}
+
+/// A setting's value.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Settings {
+    /// `$XDG_CONFIG_HOME/shy/config.toml`, or
+    /// `~/.config/shy/config.toml` if that's not set.
+    pub fn default_path() -> PathBuf {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"),
+        }
+        .join("shy")
+        .join("config.toml")
+    }
+
+    /// Load settings from a file. A missing file is just the defaults.
+    pub fn load(path: &Path) -> io::Result<Settings> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Settings::parse_from(&text, &path.display().to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Parse the text of a settings file.
+    pub fn parse(text: &str) -> Settings {
+        Settings::parse_from(text, "<settings>")
+    }
+
+    /// The theme with the `[colors]` styles on top.
+    pub fn styles(&self) -> Theme {
+        let mut theme = self.theme.clone();
+        for (slot, style) in &self.colors {
+            theme.set(slot, style).expect("checked when read");
+        }
+        theme
+    }
+
+    /// Parse settings, naming `source` in any warnings.
+    fn parse_from(text: &str, source: &str) -> Settings {
+        let mut settings = Settings::default();
+        let mut section = String::new();
+        for (i, line) in text.lines().enumerate() {
+            let result = match parse_line(line) {
+                Ok(Line::Blank) => Ok(()),
+                Ok(Line::Section(name)) => {
+                    section = name;
+                    Ok(())
+                }
+                Ok(Line::Pair(key, value)) => settings.set(&section, &key, value),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                settings
+                    .warnings
+                    .push(format!("{}:{}: {}", source, i + 1, e));
+            }
+        }
+        settings
+    }
+
+    /// Set one `key = value` from the given section.
+    fn set(&mut self, section: &str, key: &str, value: Value) -> Result<(), String> {
+        match (section, key) {
+            ("", "config") => self.config = value.string(key)?,
+            ("", "search") => self.search = value.boolean(key)?,
+            ("", "sort") => self.sort = value.string(key)?.parse().map_err(|e| format!("{}", e))?,
+            ("", "view") => self.view = value.string(key)?.parse().map_err(|e| format!("{}", e))?,
+            ("", "preview") => self.preview = value.boolean(key)?,
+            ("", "jump") => match value.integer(key)? {
+                n if n > 0 => self.jump = n as usize,
+                _ => return Err("jump must be at least 1".into()),
+            },
+            ("", "theme") => self.theme = value.string(key)?.parse()?,
+            ("", "color") => {
+                self.color = value.string(key)?.parse().map_err(|e| format!("{}", e))?
+            }
+            ("colors", slot) => {
+                let style = value.string(key)?;
+                Theme::default().set(slot, &style)?; // check it now, for the line number
+                self.colors.push((slot.to_string(), style));
+            }
+            ("", "history") => self.history = value.boolean(key)?,
+            ("", "match_exec") => self.options.match_exec = value.boolean(key)?,
+            ("", "strict") => self.options.lenient = !value.boolean(key)?,
+            ("launch", "template") => {
+                self.launcher.default = value.string(key)?.parse().map_err(|e| format!("{}", e))?
+            }
+            ("launch.hosts", patterns) => {
+                let template = value.string(key)?.parse().map_err(|e| format!("{}", e))?;
+                self.launcher
+                    .rules
+                    .push((Pattern::list(patterns), template));
+            }
+            (section, key) if section.starts_with("keys.") => {
+                let command = value.string(key)?;
+                self.keymap.bind(&section["keys.".len()..], key, &command)?
+            }
+            ("", key) => return Err(format!("unknown setting: {}", key)),
+            (section, key) => return Err(format!("unknown setting: {}.{}", section, key)),
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    fn string(self, key: &str) -> Result<String, String> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(format!("{} should be a string", key)),
+        }
+    }
+
+    fn boolean(self, key: &str) -> Result<bool, String> {
+        match self {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(format!("{} should be true or false", key)),
+        }
+    }
+
+    fn integer(self, key: &str) -> Result<i64, String> {
+        match self {
+            Value::Integer(n) => Ok(n),
+            _ => Err(format!("{} should be a number", key)),
+        }
+    }
+}
+
+/// One line of a settings file.
+#[derive(Debug, PartialEq)]
+enum Line {
+    Blank,
+    Section(String),
+    Pair(String, Value),
+}
+
+/// Parse a single line: a comment, `[section]`, or `key = value`.
+fn parse_line(line: &str) -> Result<Line, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Line::Blank);
+    }
+
+    if let Some(rest) = line.strip_prefix('[') {
+        let end = rest.find(']').ok_or("missing ]")?;
+        let name = rest[..end].trim();
+        if name.is_empty() || name.starts_with('[') {
+            return Err(format!("unsupported section: {}", line));
+        }
+        end_of_line(&rest[end + 1..])?;
+        return Ok(Line::Section(name.to_string()));
+    }
+
+    let (key, rest) = if line.starts_with('"') || line.starts_with('\'') {
+        parse_string(line)?
+    } else {
+        let end = line
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(line.len());
+        if end == 0 {
+            return Err(format!("expected a setting, found: {}", line));
+        }
+        (line[..end].to_string(), &line[end..])
+    };
+    let rest = rest
+        .trim_start()
+        .strip_prefix('=')
+        .ok_or_else(|| format!("missing = after {}", key))?
+        .trim_start();
+
+    let (value, rest) = if rest.starts_with('"') || rest.starts_with('\'') {
+        let (s, rest) = parse_string(rest)?;
+        (Value::String(s), rest)
+    } else {
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '#')
+            .unwrap_or(rest.len());
+        let value = match &rest[..end] {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            "" => return Err(format!("missing value for {}", key)),
+            word => Value::Integer(
+                word.replace('_', "")
+                    .parse()
+                    .map_err(|_| format!("bad value for {}: {}", key, word))?,
+            ),
+        };
+        (value, &rest[end..])
+    };
+    end_of_line(rest)?;
+    Ok(Line::Pair(key, value))
+}
+
+/// Parse a "basic" or 'literal' string from the start of `s`,
+/// returning it and the rest of the line.
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+    let mut chars = s.char_indices();
+    let quote = chars.next().map(|(_, c)| c).unwrap_or('"');
+    let mut out = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((out, &s[i + 1..])),
+            '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('\\') => out.push('\\'),
+                Some('"') => out.push('"'),
+                Some(c) => return Err(format!("unknown escape: \\{}", c)),
+                None => break,
+            },
+            c => out.push(c),
+        }
+    }
+    Err("unterminated string".into())
+}
+
+/// Only whitespace or a comment can follow a value.
+fn end_of_line(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected text: {}", rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{keymap::Command, ssh_config::HostEntry, tui::Mode},
+        termion::event::Key,
+    };
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(Ok(Line::Blank), parse_line("  # comment"));
+        assert_eq!(
+            Ok(Line::Section("launch.hosts".into())),
+            parse_line("[launch.hosts] # hi")
+        );
+        assert_eq!(
+            Ok(Line::Pair("jump".into(), Value::Integer(10))),
+            parse_line("jump = 1_0")
+        );
+        assert_eq!(
+            Ok(Line::Pair("preview".into(), Value::Boolean(true))),
+            parse_line("preview=true")
+        );
+        assert_eq!(
+            Ok(Line::Pair(
+                "*.lan".into(),
+                Value::String("a \"b\" #c".into())
+            )),
+            parse_line("\"*.lan\" = \"a \\\"b\\\" #c\" # comment")
+        );
+        assert_eq!(
+            Ok(Line::Pair("config".into(), Value::String("C:\\ssh".into()))),
+            parse_line("config = 'C:\\ssh'")
+        );
+        assert!(parse_line("[[rules]]").is_err());
+        assert!(parse_line("jump = ").is_err());
+        assert!(parse_line("jump = five").is_err());
+        assert!(parse_line("sort = \"config").is_err());
+        assert!(parse_line("sort = \"config\" extra").is_err());
+        assert!(parse_line("= 1").is_err());
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings = Settings::parse(
+            "config = \"~/.ssh/work\"\n\
+             sort = \"frecency\"\n\
+             view = \"files\"\n\
+             preview = true\n\
+             jump = 10\n\
+             strict = true\n\
+             theme = \"light\"\n\
+             color = \"never\"\n\
+             \n\
+             [launch]\n\
+             template = \"mosh {alias}\"\n\
+             [launch.hosts]\n\
+             \"*.lan, !nas\" = \"et {hostname}\"\n\
+             [keys.search]\n\
+             ctrl-j = \"down\"\n\
+             [colors]\n\
+             selection = \"bold #ff8700\"\n",
+        );
+        assert!(settings.warnings.is_empty(), "{:?}", settings.warnings);
+        assert_eq!("~/.ssh/work", settings.config);
+        assert_eq!(Sort::Frecency, settings.sort);
+        assert_eq!(View::Files, settings.view);
+        assert!(settings.preview);
+        assert_eq!(10, settings.jump);
+        assert!(!settings.options.lenient);
+        let argv = |alias| {
+            let host = HostEntry::new(alias);
+            settings.launcher.template(alias).argv(&host, &[])
+        };
+        assert_eq!(vec!["mosh", "web"], argv("web"));
+        assert_eq!(vec!["et", "web.lan"], argv("web.lan"));
+        assert_eq!(vec!["mosh", "nas"], argv("nas"));
+        assert_eq!(
+            Some(Command::Down),
+            settings.keymap.get(&Mode::Search, Key::Ctrl('j'))
+        );
+        let mut theme = Theme::named("light").unwrap();
+        theme.selection = "bold #ff8700".parse().unwrap();
+        assert_eq!(theme, settings.styles());
+        assert_eq!(ColorChoice::Never, settings.color);
+
+        // picking another theme, like --theme does, keeps [colors]
+        let mut settings = settings;
+        settings.theme = "nord".parse().unwrap();
+        let mut theme = Theme::named("nord").unwrap();
+        theme.selection = "bold #ff8700".parse().unwrap();
+        assert_eq!(theme, settings.styles());
+    }
+
+    #[test]
+    fn test_warnings() {
+        let settings = Settings::parse(
+            "colour = \"red\"\n\
+             jump = 0\n\
+             preview = \"yes\"\n\
+             sort = \"random\"\n\
+             search = true\n\
+             [launch]\n\
+             template = \"ssh {alias\"\n\
+             shell = \"zsh\"\n\
+             [keys.nav]\n\
+             j = \"jump\"\n\
+             [keys.insert]\n\
+             j = \"down\"\n\
+             [colors]\n\
+             host = \"bright-mauve\"\n\
+             cursor = \"blue\"\n",
+        );
+        assert_eq!(
+            vec![
+                "<settings>:1: unknown setting: colour",
+                "<settings>:2: jump must be at least 1",
+                "<settings>:3: preview should be true or false",
+                "<settings>:4: unknown sort: random (try config or frecency)",
+                "<settings>:7: ssh {alias: missing closing }",
+                "<settings>:8: unknown setting: launch.shell",
+                "<settings>:10: unknown command: jump",
+                "<settings>:12: unknown mode: insert (try nav, search, menu, prompt, or warnings)",
+                "<settings>:14: unknown color: bright-mauve",
+                "<settings>:15: unknown theme slot: cursor (try host, selection, status, prompt, \
+                 found, missed, matched, header, dim, warning)",
+            ],
+            settings.warnings
+        );
+        // good settings still apply
+        assert!(settings.search);
+        assert_eq!(5, settings.jump);
+    }
+
+    #[test]
+    fn test_load() {
+        let settings = Settings::load(Path::new("./tests/no_such_file.toml")).unwrap();
+        assert_eq!("~/.ssh/config", settings.config);
+        assert!(settings.warnings.is_empty());
+    }
+}
@@ -1,103 +1,1124 @@
 use {
     indexmap::IndexMap,
-    std::{env, fs, io},
+    std::{
+        env, error, fmt, fs, io,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
 };
 
-pub type HostMap = IndexMap<String, String>;
+pub type HostMap = IndexMap<String, HostEntry>;
 
-/// For now just load the hostnames and their labels.
+/// Everything we know about a single Host from the config.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostEntry {
+    /// The alias from the `Host` line. This is what we hand to ssh.
+    pub name: String,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<String>,
+    pub proxy_jump: Option<String>,
+    pub proxy_command: Option<String>,
+    pub local_forwards: Vec<Forward>,
+    pub remote_forwards: Vec<Forward>,
+    /// Every other keyword (lowercased) and its arguments.
+    pub options: IndexMap<String, Vec<String>>,
+    /// Where the alias was first seen.
+    pub source: Option<Source>,
+    /// Comments from the `Host` stanzas that name this alias, without
+    /// the `#`.
+    pub comments: Vec<String>,
+    /// From `# shy: tags=prod,db` comments.
+    pub tags: Vec<String>,
+    /// From `# shy: group="EU Cluster"` comments.
+    pub group: Option<String>,
+    /// From `# shy: desc="primary postgres"` comments.
+    pub desc: Option<String>,
+}
+
+/// A spot in a config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Source {
+    /// The file, unless we were parsing a string.
+    pub path: Option<PathBuf>,
+    /// 1-based line number.
+    pub line: usize,
+}
+
+/// A `LocalForward` or `RemoteForward`. Remote forwards without a
+/// target are dynamic (SOCKS) forwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forward {
+    pub listen: String,
+    pub target: Option<String>,
+}
+
+impl HostEntry {
+    /// Create an empty entry for the given alias.
+    pub fn new(name: &str) -> HostEntry {
+        HostEntry {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The real hostname. If there's no HostName option, ssh uses the
+    /// alias itself.
+    pub fn hostname(&self) -> &str {
+        self.hostname.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The user ssh will log in as: User, or whoever's running shy.
+    pub fn remote_user(&self) -> String {
+        self.user.clone().unwrap_or_else(local_user)
+    }
+
+    /// The first argument of some other option, if it's set.
+    pub fn option(&self, keyword: &str) -> Option<&str> {
+        self.options
+            .get(&keyword.to_lowercase())
+            .and_then(|args| args.first())
+            .map(|s| s.as_ref())
+    }
+
+    /// Apply a single `key value` option, as if it were a line in the
+    /// config.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ParseError> {
+        self.apply(&Directive::new(key, value)?);
+        Ok(())
+    }
+
+    /// Apply a `shy: key=value ...` comment, returning false if it's
+    /// just a regular comment. Tags add up; like options, the first
+    /// group and desc win. Unknown keys are ignored.
+    pub fn annotate(&mut self, comment: &str) -> bool {
+        let rest = match comment.trim_start().strip_prefix("shy:") {
+            Some(rest) => rest,
+            None => return false,
+        };
+        let args = match split_args(rest, 0) {
+            Ok(args) => args,
+            Err(_) => return false,
+        };
+        for (_, arg) in args {
+            let mut parts = arg.splitn(2, '=');
+            let (key, value) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+            match key {
+                "tags" | "tag" => {
+                    for tag in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                        add_once(&mut self.tags, tag.to_string());
+                    }
+                }
+                "group" if !value.is_empty() => set_once(&mut self.group, value),
+                "desc" if !value.is_empty() => set_once(&mut self.desc, value),
+                _ => {}
+            }
+        }
+        true
+    }
+
+    /// Apply a single option. Like OpenSSH, keywords are
+    /// case-insensitive and the first value wins for options that can
+    /// only be set once. IdentityFile, forwards and a few others
+    /// accumulate, minus duplicates.
+    pub fn apply(&mut self, directive: &Directive) {
+        let value = directive.args.first().map(|s| s.as_ref()).unwrap_or("");
+        match directive.keyword.as_ref() {
+            "hostname" => set_once(&mut self.hostname, value),
+            "user" => set_once(&mut self.user, value),
+            "port" => {
+                // bad ports are caught when the directive is parsed
+                if let Ok(port) = value.parse() {
+                    self.port.get_or_insert(port);
+                }
+            }
+            "identityfile" => add_once(&mut self.identity_files, value.to_string()),
+            "proxyjump" => set_once(&mut self.proxy_jump, value),
+            // commands get the rest of the line, quotes and all
+            "proxycommand" => set_once(&mut self.proxy_command, &directive.raw),
+            "localforward" => add_once(&mut self.local_forwards, Forward::new(&directive.args)),
+            "remoteforward" => add_once(&mut self.remote_forwards, Forward::new(&directive.args)),
+            "sendenv" | "certificatefile" | "dynamicforward" => {
+                let list = self.options.entry(directive.keyword.clone()).or_default();
+                for arg in &directive.args {
+                    add_once(list, arg.clone());
+                }
+            }
+            _ => {
+                self.options
+                    .entry(directive.keyword.clone())
+                    .or_insert_with(|| directive.args.clone());
+            }
+        }
+    }
+}
+
+impl Forward {
+    /// Build a forward from its `listen [target]` arguments.
+    fn new(args: &[String]) -> Forward {
+        Forward {
+            listen: args.first().cloned().unwrap_or_default(),
+            target: args.get(1).cloned(),
+        }
+    }
+}
+
+/// Add to a list option, ignoring duplicates like OpenSSH does.
+fn add_once<T: PartialEq>(list: &mut Vec<T>, value: T) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+/// Only set an option if it hasn't been set yet.
+fn set_once(option: &mut Option<String>, value: &str) {
+    option.get_or_insert_with(|| value.to_string());
+}
+
+/// OpenSSH gives up after this many nested Includes, and so do we.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Load every concrete Host and its effective options. Any `Include`
+/// directives are followed, with relative paths resolved against
+/// `~/.ssh` just like OpenSSH does.
 pub fn load_ssh_config(path: &str) -> io::Result<HostMap> {
-    parse_ssh_config(&fs::read_to_string(
-        path.replace('~', &env::var("HOME").expect("$HOME must be set")),
-    )?)
+    Ok(Config::load(path)?.hosts())
 }
 
 /// Parse .ssh/config to a (sorted) map.
 pub fn parse_ssh_config<S: AsRef<str>>(config: S) -> io::Result<HostMap> {
-    let config = config.as_ref();
-    let mut map = HostMap::new();
-
-    let mut token = String::new(); // the token we're parsing
-    let mut line = vec![]; // current line
-    let mut skip_line = false; // skip until EOL for comments
-    let mut stanza = String::new(); // ssh config is broken into stanzas
-    let mut key = true; // parsing the key or the value?
-
-    for c in config.chars() {
-        if skip_line {
-            if c == '\n' {
-                skip_line = false;
+    Ok(Config::parse(config)?.hosts())
+}
+
+/// How to parse a config and resolve each host's options.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Run the commands in `Match exec` criteria. When this is off,
+    /// any Match block with an `exec` never applies.
+    pub match_exec: bool,
+    /// Skip lines we can't parse instead of failing, and collect them
+    /// in `Config::warnings`.
+    pub lenient: bool,
+}
+
+/// A parsed ssh config: every stanza, in the order OpenSSH reads them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub stanzas: Vec<Stanza>,
+    /// Concrete Host names and where they first appear, in order.
+    pub aliases: IndexMap<String, Source>,
+    /// Lines that were skipped in lenient mode.
+    pub warnings: Vec<ParseError>,
+}
+
+/// A `Host` or `Match` line and the options that follow it. Options
+/// that appear before the first one live in a `Host *` stanza.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stanza {
+    pub condition: Condition,
+    pub options: Vec<Directive>,
+    /// Comment lines just above the `Host` or `Match` line, or inside
+    /// the stanza.
+    pub comments: Vec<String>,
+    /// The conditions of the stanzas any `Include` that brought this
+    /// one in was inside. They all have to match too.
+    pub within: Vec<Condition>,
+}
+
+/// A single `Keyword arguments...` line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    /// Always lowercase, since keywords are case-insensitive.
+    pub keyword: String,
+    /// Arguments, with quotes removed.
+    pub args: Vec<String>,
+    /// Everything after the keyword, untouched. Options like
+    /// ProxyCommand take the rest of the line as-is.
+    pub raw: String,
+    /// Where the keyword and each argument start, for errors.
+    columns: Vec<usize>,
+}
+
+/// A line of config we couldn't make sense of. Parse errors are
+/// wrapped in an `io::Error` when loading a config, so they can be
+/// pulled back out with `get_ref()` and `downcast_ref()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// The file the error is in, unless we were parsing a string.
+    pub path: Option<PathBuf>,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, in chars.
+    pub column: usize,
+    /// The offending line, for showing context.
+    pub text: String,
+    pub kind: ParseErrorKind,
+}
+
+/// What's wrong with the line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnterminatedQuote,
+    /// A keyword with no value, like a bare `Host`.
+    MissingValue(String),
+    InvalidPort(String),
+    /// A `Match` criterion that needs an argument, like `user`.
+    MissingCriterionArg(String),
+    /// `Match` on its own.
+    NoCriteria,
+    TooManyIncludes,
+}
+
+/// When a stanza applies.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// `Host` patterns, matched against the alias.
+    Host(Vec<Pattern>),
+    /// `Match` criteria, which all need to match.
+    Match(Vec<Criterion>),
+}
+
+/// A single pattern from a `Host` line, like `docker*` or `!docker3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub pattern: String,
+    pub negated: bool,
+}
+
+/// A single criterion from a `Match` line, like `host *.lan` or
+/// `!user root`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Criterion {
+    pub kind: CriterionKind,
+    pub negated: bool,
+}
+
+/// The criteria OpenSSH knows how to `Match` on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CriterionKind {
+    All,
+    Canonical,
+    Final,
+    Exec(String),
+    Host(Vec<Pattern>),
+    OriginalHost(Vec<Pattern>),
+    User(Vec<Pattern>),
+    LocalUser(Vec<Pattern>),
+    /// Newer criteria we can't check, like `tagged` or `localnetwork`.
+    Other(String, String),
+}
+
+/// What we know about a host while resolving its options.
+struct Context<'a> {
+    entry: &'a HostEntry,
+    options: &'a Options,
+    final_pass: bool,
+}
+
+impl Config {
+    /// Load and parse a config file, following Includes.
+    pub fn load(path: &str) -> io::Result<Config> {
+        Config::load_with(path, &Options::default())
+    }
+
+    /// Load and parse a config file, optionally in lenient mode.
+    pub fn load_with(path: &str, options: &Options) -> io::Result<Config> {
+        let mut parser = Parser::new(home_dir().join(".ssh"));
+        parser.lenient = options.lenient;
+        parser.parse_file(&expand_tilde(path))?;
+        Ok(parser.config)
+    }
+
+    /// Parse the text of a config file.
+    pub fn parse<S: AsRef<str>>(config: S) -> io::Result<Config> {
+        Config::parse_with(config, &Options::default())
+    }
+
+    /// Parse the text of a config file, optionally in lenient mode.
+    pub fn parse_with<S: AsRef<str>>(config: S, options: &Options) -> io::Result<Config> {
+        let mut parser = Parser::new(home_dir().join(".ssh"));
+        parser.lenient = options.lenient;
+        parser.parse(config.as_ref())?;
+        Ok(parser.config)
+    }
+
+    /// Compute the options ssh would really use for `alias` by walking
+    /// every stanza that matches it. The first value wins.
+    pub fn resolve(&self, alias: &str) -> HostEntry {
+        self.resolve_with(alias, &Options::default())
+    }
+
+    /// Like `resolve()`, with control over how Match blocks are
+    /// evaluated. If any block uses `final` or `canonical`, the config
+    /// is read twice, like OpenSSH does.
+    pub fn resolve_with(&self, alias: &str, options: &Options) -> HostEntry {
+        let mut entry = HostEntry::new(alias);
+        entry.source = self.aliases.get(alias).cloned();
+        for stanza in &self.stanzas {
+            let named = stanza
+                .patterns()
+                .iter()
+                .any(|p| !p.negated && p.is_concrete() && p.pattern == alias);
+            if named {
+                for comment in &stanza.comments {
+                    if !entry.annotate(comment) {
+                        entry.comments.push(comment.clone());
+                    }
+                }
+            }
+        }
+        self.apply(&mut entry, options, false);
+        if self.stanzas.iter().any(Stanza::wants_final_pass) {
+            self.apply(&mut entry, options, true);
+        }
+        entry
+    }
+
+    /// Resolve every concrete host.
+    pub fn hosts(&self) -> HostMap {
+        self.hosts_with(&Options::default())
+    }
+
+    /// Resolve every concrete host, with control over Match blocks.
+    pub fn hosts_with(&self, options: &Options) -> HostMap {
+        self.aliases
+            .keys()
+            .map(|alias| (alias.clone(), self.resolve_with(alias, options)))
+            .collect()
+    }
+
+    /// Apply the options from every matching stanza, in order.
+    fn apply(&self, entry: &mut HostEntry, options: &Options, final_pass: bool) {
+        for stanza in &self.stanzas {
+            let ctx = Context {
+                entry,
+                options,
+                final_pass,
+            };
+            if stanza.matches(&ctx) {
+                for directive in &stanza.options {
+                    entry.apply(directive);
+                }
+            }
+        }
+    }
+}
+
+impl Stanza {
+    /// Create a stanza from the patterns on a `Host` line, which can
+    /// be separated by whitespace or commas.
+    pub fn host(patterns: &str) -> Stanza {
+        Stanza {
+            condition: Condition::Host(Pattern::list(patterns)),
+            options: vec![],
+            comments: vec![],
+            within: vec![],
+        }
+    }
+
+    /// A stanza that never applies. Used in place of a `Host` or
+    /// `Match` line we couldn't parse, so the options after it don't
+    /// end up somewhere they don't belong.
+    fn never() -> Stanza {
+        Stanza {
+            condition: Condition::Host(vec![]),
+            options: vec![],
+            comments: vec![],
+            within: vec![],
+        }
+    }
+
+    /// Create a stanza from the criteria on a `Match` line.
+    pub fn parse_match(directive: &Directive) -> Result<Stanza, ParseError> {
+        Ok(Stanza {
+            condition: Condition::Match(Criterion::parse_all(directive)?),
+            options: vec![],
+            comments: vec![],
+            within: vec![],
+        })
+    }
+
+    /// The `Host` patterns, if this is a Host stanza.
+    pub fn patterns(&self) -> &[Pattern] {
+        match &self.condition {
+            Condition::Host(patterns) => patterns,
+            Condition::Match(_) => &[],
+        }
+    }
+
+    /// Does this stanza need OpenSSH's second, "final" pass?
+    fn wants_final_pass(&self) -> bool {
+        self.within
+            .iter()
+            .chain(Some(&self.condition))
+            .any(Condition::wants_final_pass)
+    }
+
+    /// Does this stanza, and every block it was included from, apply
+    /// to the host we're resolving?
+    fn matches(&self, ctx: &Context) -> bool {
+        self.within
+            .iter()
+            .chain(Some(&self.condition))
+            .all(|c| c.matches(ctx))
+    }
+}
+
+impl Condition {
+    /// Could this apply to a host named `alias`? Host patterns only
+    /// need the name, but a Match could depend on anything.
+    fn could_match(&self, alias: &str) -> bool {
+        match self {
+            Condition::Host(patterns) => matches_patterns(patterns, alias),
+            Condition::Match(_) => true,
+        }
+    }
+
+    /// Is this a `Match` with `final` or `canonical`?
+    fn wants_final_pass(&self) -> bool {
+        match self {
+            Condition::Host(_) => false,
+            Condition::Match(criteria) => criteria
+                .iter()
+                .any(|c| matches!(c.kind, CriterionKind::Final | CriterionKind::Canonical)),
+        }
+    }
+
+    /// Does this apply to the host we're resolving? Match blocks with
+    /// a criterion we can't check never apply.
+    fn matches(&self, ctx: &Context) -> bool {
+        match self {
+            Condition::Host(patterns) => matches_patterns(patterns, &ctx.entry.name),
+            Condition::Match(criteria) => criteria
+                .iter()
+                .all(|c| c.matches(ctx).map(|m| m != c.negated).unwrap_or(false)),
+        }
+    }
+}
+
+impl Pattern {
+    /// Parse a pattern, noting if it starts with `!`.
+    pub fn new(pattern: &str) -> Pattern {
+        let negated = pattern.starts_with('!');
+        Pattern {
+            pattern: pattern.trim_start_matches('!').to_string(),
+            negated,
+        }
+    }
+
+    /// Parse a list of patterns separated by whitespace or commas.
+    pub fn list(patterns: &str) -> Vec<Pattern> {
+        patterns
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|p| !p.is_empty())
+            .map(Pattern::new)
+            .collect()
+    }
+
+    /// Is this the name of a real host, rather than a wildcard or
+    /// negated pattern?
+    pub fn is_concrete(&self) -> bool {
+        !self.negated && !self.pattern.contains('*') && !self.pattern.contains('?')
+    }
+}
+
+impl Criterion {
+    /// Parse every criterion on a `Match` line.
+    fn parse_all(directive: &Directive) -> Result<Vec<Criterion>, ParseError> {
+        let mut words = directive.args.iter().enumerate();
+        let mut out = vec![];
+        while let Some((i, word)) = words.next() {
+            let negated = word.starts_with('!');
+            let keyword = word.trim_start_matches('!').to_lowercase();
+            let kind = match keyword.as_ref() {
+                "all" => CriterionKind::All,
+                "canonical" => CriterionKind::Canonical,
+                "final" => CriterionKind::Final,
+                _ => {
+                    let arg = match words.next() {
+                        Some((_, arg)) => arg,
+                        None => {
+                            return Err(
+                                directive.error(i, ParseErrorKind::MissingCriterionArg(keyword))
+                            )
+                        }
+                    };
+                    match keyword.as_ref() {
+                        "exec" => CriterionKind::Exec(arg.to_string()),
+                        "host" => CriterionKind::Host(Pattern::list(arg)),
+                        "originalhost" => CriterionKind::OriginalHost(Pattern::list(arg)),
+                        "user" => CriterionKind::User(Pattern::list(arg)),
+                        "localuser" => CriterionKind::LocalUser(Pattern::list(arg)),
+                        _ => CriterionKind::Other(keyword, arg.to_string()),
+                    }
+                }
+            };
+            out.push(Criterion { kind, negated });
+        }
+
+        if out.is_empty() {
+            return Err(directive.error(0, ParseErrorKind::NoCriteria));
+        }
+        Ok(out)
+    }
+
+    /// Does this criterion match, ignoring `!`? `None` means we
+    /// can't tell without connecting or running something.
+    fn matches(&self, ctx: &Context) -> Option<bool> {
+        let entry = ctx.entry;
+        match &self.kind {
+            CriterionKind::All => Some(true),
+            CriterionKind::Final => Some(ctx.final_pass),
+            // we never canonicalize ourselves, but ssh will
+            CriterionKind::Canonical => Some(
+                ctx.final_pass
+                    && entry
+                        .option("canonicalizehostname")
+                        .map(|v| v.eq_ignore_ascii_case("yes") || v.eq_ignore_ascii_case("always"))
+                        .unwrap_or(false),
+            ),
+            CriterionKind::Host(patterns) => Some(matches_patterns(patterns, entry.hostname())),
+            CriterionKind::OriginalHost(patterns) => Some(matches_patterns(patterns, &entry.name)),
+            CriterionKind::User(patterns) => Some(matches_patterns(
+                patterns,
+                entry.user.as_deref().unwrap_or(&local_user()),
+            )),
+            CriterionKind::LocalUser(patterns) => Some(matches_patterns(patterns, &local_user())),
+            CriterionKind::Exec(cmd) if ctx.options.match_exec => Some(
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(expand_tokens(cmd, entry))
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .map(|s| s.success())
+                    .unwrap_or(false),
+            ),
+            CriterionKind::Exec(_) | CriterionKind::Other(..) => None,
+        }
+    }
+}
+
+impl Directive {
+    /// Build a directive from a keyword and its (unsplit) value.
+    pub fn new(keyword: &str, value: &str) -> Result<Directive, ParseError> {
+        Directive::build(keyword, value, 1, keyword.chars().count() + 2)
+    }
+
+    /// Parse a line of config. Blank lines and comments are `None`.
+    /// The keyword can be followed by whitespace, `=`, or both.
+    pub fn parse(line: &str) -> Result<Option<Directive>, ParseError> {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(None);
+        }
+        let column = line.chars().count() - trimmed.chars().count() + 1;
+
+        let end = trimmed
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(trimmed.len());
+        let (keyword, rest) = trimmed.split_at(end);
+        let value = rest.trim_start();
+        let value = value.strip_prefix('=').unwrap_or(value);
+        let value_column = line.chars().count() - value.chars().count() + 1;
+
+        Directive::build(keyword, value, column, value_column)
+            .map(Some)
+            .map_err(|mut e| {
+                e.text = line.to_string();
+                e
+            })
+    }
+
+    /// Build and check a directive, given the columns where the
+    /// keyword and value start.
+    fn build(
+        keyword: &str,
+        value: &str,
+        column: usize,
+        value_column: usize,
+    ) -> Result<Directive, ParseError> {
+        let keyword = keyword.to_lowercase();
+        let args = split_args(value, value_column).map_err(|(column, kind)| ParseError {
+            path: None,
+            line: 1,
+            column,
+            text: format!("{} {}", keyword, value),
+            kind,
+        })?;
+
+        let directive = Directive {
+            raw: value.trim().to_string(),
+            columns: Some(column)
+                .into_iter()
+                .chain(args.iter().map(|(col, _)| *col))
+                .collect(),
+            args: args.into_iter().map(|(_, arg)| arg).collect(),
+            keyword,
+        };
+        directive.check()?;
+        Ok(directive)
+    }
+
+    /// Catch values ssh would reject.
+    fn check(&self) -> Result<(), ParseError> {
+        if self.args.is_empty() {
+            let mut err = self.error(0, ParseErrorKind::MissingValue(self.keyword.clone()));
+            err.column += self.keyword.chars().count();
+            return Err(err);
+        }
+        if self.keyword == "port" && self.args[0].parse::<u16>().is_err() {
+            return Err(self.error(0, ParseErrorKind::InvalidPort(self.args[0].clone())));
+        }
+        Ok(())
+    }
+
+    /// An error pointing at one of our arguments, or at the keyword if
+    /// there's no such argument.
+    fn error(&self, arg: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            path: None,
+            line: 1,
+            column: *self.columns.get(arg + 1).unwrap_or(&self.columns[0]),
+            text: format!("{} {}", self.keyword, self.raw),
+            kind,
+        }
+    }
+}
+
+/// Split a value into arguments, like OpenSSH does. Arguments are
+/// separated by whitespace, can be wrapped in double or single
+/// quotes, and a backslash escapes the next quote, backslash, or
+/// space. An unquoted `#` starts a comment. Each argument comes back
+/// with the column it starts at, counting from `column`.
+pub(crate) fn split_args(
+    value: &str,
+    column: usize,
+) -> Result<Vec<(usize, String)>, (usize, ParseErrorKind)> {
+    let mut args = vec![];
+    let mut chars = value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| (i + column, c))
+        .peekable();
+
+    loop {
+        while chars
+            .peek()
+            .map(|(_, c)| c.is_whitespace())
+            .unwrap_or(false)
+        {
+            chars.next();
+        }
+        let start = match chars.peek() {
+            None | Some((_, '#')) => break,
+            Some((col, _)) => *col,
+        };
+
+        let mut arg = String::new();
+        let mut quote = None;
+        while let Some((col, c)) = chars.next() {
+            match c {
+                '\\' if matches!(
+                    chars.peek(),
+                    Some((_, '"')) | Some((_, '\'')) | Some((_, '\\')) | Some((_, ' '))
+                ) =>
+                {
+                    arg.push(chars.next().unwrap().1)
+                }
+                '"' | '\'' if quote.is_none() => quote = Some((col, c)),
+                c if quote.map(|(_, q)| q) == Some(c) => quote = None,
+                c if quote.is_none() && c.is_whitespace() => break,
+                c => arg.push(c),
             }
+        }
+        if let Some((col, _)) = quote {
+            return Err((col, ParseErrorKind::UnterminatedQuote));
+        }
+        args.push((start, arg));
+    }
+
+    Ok(args)
+}
+
+impl ParseError {
+    /// Fill in where the error happened.
+    fn located(mut self, path: Option<PathBuf>, line: usize, text: &str) -> ParseError {
+        self.path = path;
+        self.line = line;
+        self.text = text.to_string();
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnterminatedQuote => write!(f, "unterminated quote"),
+            ParseErrorKind::MissingValue(keyword) => write!(f, "{} needs a value", keyword),
+            ParseErrorKind::InvalidPort(port) => write!(f, "bad port: {}", port),
+            ParseErrorKind::MissingCriterionArg(criterion) => {
+                write!(f, "Match {} needs an argument", criterion)
+            }
+            ParseErrorKind::NoCriteria => write!(f, "Match needs at least one criterion"),
+            ParseErrorKind::TooManyIncludes => write!(f, "too many nested includes"),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(err: ParseError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// At least one pattern needs to match, and none of the negated ones
+/// can. This is OpenSSH's pattern-list logic.
+pub fn matches_patterns(patterns: &[Pattern], s: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if wildcard_match(&pattern.pattern, s) {
+            if pattern.negated {
+                return false;
+            }
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// Expand the `%` tokens OpenSSH supports in `Match exec` commands.
+fn expand_tokens(cmd: &str, entry: &HostEntry) -> String {
+    let mut out = String::new();
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
             continue;
         }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some('h') => out.push_str(entry.hostname()),
+            Some('n') => out.push_str(&entry.name),
+            Some('p') => out.push_str(&entry.port.unwrap_or(22).to_string()),
+            Some('r') => out.push_str(&entry.remote_user()),
+            Some('u') => out.push_str(&local_user()),
+            Some('d') => out.push_str(&home_dir().to_string_lossy()),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// The user running shy.
+fn local_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default()
+}
+
+/// Walks one or more config files, following `Include` directives
+/// as it goes so hosts end up in the same order OpenSSH sees them.
+struct Parser {
+    config: Config,
+    ssh_dir: PathBuf,       // relative Include paths start here
+    stack: Vec<PathBuf>,    // files we're in the middle of reading
+    line: usize,            // line number we're on, for errors
+    text: String,           // text of the line we're on
+    lenient: bool,          // skip bad lines instead of failing
+    comments: Vec<String>,  // comments we haven't given a stanza yet
+    within: Vec<Condition>, // blocks the Includes we're in appeared in
+}
+
+impl Parser {
+    fn new(ssh_dir: PathBuf) -> Parser {
+        Parser {
+            config: Config {
+                stanzas: vec![Stanza::host("*")],
+                ..Default::default()
+            },
+            ssh_dir,
+            stack: vec![],
+            line: 0,
+            text: String::new(),
+            lenient: false,
+            comments: vec![],
+            within: vec![],
+        }
+    }
+
+    /// Parse a file on disk. Files we're already in the middle of
+    /// reading are skipped, which breaks Include cycles.
+    fn parse_file(&mut self, path: &Path) -> io::Result<()> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.stack.contains(&path) {
+            return Ok(());
+        }
+
+        let config = fs::read_to_string(&path)?;
+        self.stack.push(path);
+        let res = self.parse(&config);
+        self.stack.pop();
+        res
+    }
 
-        if c == '#' {
-            // skip comments
-            skip_line = true;
-            if !token.is_empty() {
-                line.push(token);
-                token = String::new();
+    /// Parse the text of a config file.
+    fn parse(&mut self, config: &str) -> io::Result<()> {
+        for (i, line) in config.lines().enumerate() {
+            self.line = i + 1;
+            self.text = line.to_string();
+            let res = match Directive::parse(line) {
+                Ok(Some(directive)) => self.parse_directive(directive),
+                Ok(None) => {
+                    self.comment(line);
+                    Ok(())
+                }
+                Err(err) => Err(self.locate(err).into()),
+            };
+            if let Err(err) = res {
+                self.recover(err, line)?;
             }
-        } else if key && (c == ' ' || c == '=') {
-            // "key = value" OR "key value" separator
-            if !token.is_empty() {
-                line.push(token);
-                token = String::new();
-                key = false;
+        }
+        self.flush_comments();
+        Ok(())
+    }
+
+    /// Hold on to a comment until we know which stanza it's for: the
+    /// next `Host` if it's right above one, otherwise the stanza it's
+    /// in. A blank line ends a run of comments.
+    fn comment(&mut self, line: &str) {
+        match line.trim().strip_prefix('#') {
+            Some(comment) => self.comments.push(comment.trim().to_string()),
+            None => self.flush_comments(),
+        }
+    }
+
+    /// Give any pending comments to the current stanza.
+    fn flush_comments(&mut self) {
+        let comments = std::mem::take(&mut self.comments);
+        self.stanza().comments.extend(comments);
+    }
+
+    /// In lenient mode, turn a parse error into a warning and carry
+    /// on. Anything else is passed along.
+    fn recover(&mut self, err: io::Error, line: &str) -> io::Result<()> {
+        let warning = match err.get_ref().and_then(|e| e.downcast_ref::<ParseError>()) {
+            Some(warning) if self.lenient => warning.clone(),
+            _ => return Err(err),
+        };
+        self.config.warnings.push(warning);
+
+        // a broken Host or Match still starts a new stanza
+        let keyword = line
+            .trim_start()
+            .split(|c: char| c.is_whitespace() || c == '=')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if keyword == "host" || keyword == "match" {
+            self.push(Stanza::never());
+        }
+        Ok(())
+    }
+
+    /// Handle a single line.
+    fn parse_directive(&mut self, directive: Directive) -> io::Result<()> {
+        if directive.keyword != "host" && directive.keyword != "match" {
+            self.flush_comments();
+        }
+        match directive.keyword.as_ref() {
+            "host" => {
+                let mut stanza = Stanza::host(&directive.args.join(" "));
+                stanza.comments = std::mem::take(&mut self.comments);
+                // every literal name on the line is a host we can launch,
+                // unless it's in an Include that can't apply to it
+                for pattern in stanza.patterns().iter().filter(|p| p.is_concrete()) {
+                    let alias = &pattern.pattern;
+                    if !self.config.aliases.contains_key(alias)
+                        && self.within.iter().all(|c| c.could_match(alias))
+                    {
+                        let source = Source {
+                            path: self.stack.last().cloned(),
+                            line: self.line,
+                        };
+                        self.config.aliases.insert(alias.clone(), source);
+                    }
+                }
+                self.push(stanza);
             }
-        } else if c == '\n' {
-            if !token.is_empty() {
-                line.push(token);
-                token = String::new();
-                key = true;
+            "match" => {
+                let mut stanza = Stanza::parse_match(&directive).map_err(|e| self.locate(e))?;
+                stanza.comments = std::mem::take(&mut self.comments);
+                self.push(stanza);
             }
+            "include" => self.include(&directive)?,
+            _ => self.stanza().options.push(directive),
+        }
+        Ok(())
+    }
+
+    /// Point an error at the line we're on.
+    fn locate(&self, err: ParseError) -> ParseError {
+        err.located(self.stack.last().cloned(), self.line, &self.text)
+    }
+
+    /// Start a new stanza, inside whatever blocks we're including from.
+    fn push(&mut self, mut stanza: Stanza) {
+        stanza.within = self.within.clone();
+        self.config.stanzas.push(stanza);
+    }
+
+    /// The stanza we're currently adding options to.
+    fn stanza(&mut self) -> &mut Stanza {
+        // there's always at least the implicit `Host *` stanza
+        self.config.stanzas.last_mut().unwrap()
+    }
+
+    /// Inline every file matched by an Include directive. Stanzas in
+    /// those files only apply when the Host or Match the Include is in
+    /// does too, and that block is restored afterwards, since that's
+    /// what OpenSSH does.
+    fn include(&mut self, directive: &Directive) -> io::Result<()> {
+        if self.stack.len() > MAX_INCLUDE_DEPTH {
+            let err = directive.error(0, ParseErrorKind::TooManyIncludes);
+            return Err(self.locate(err).into());
+        }
+
+        let stanzas = self.config.stanzas.len();
+        let condition = self.stanza().condition.clone();
+        let outer = self.within.clone();
+        self.within.push(condition.clone());
+        let res = self.include_files(directive);
+        self.within = outer;
+        res?;
+        if self.config.stanzas.len() != stanzas {
+            self.push(Stanza {
+                condition,
+                options: vec![],
+                comments: vec![],
+                within: vec![],
+            });
+        }
+        Ok(())
+    }
 
-            if line.is_empty() {
-                continue;
-            } else if line.len() != 2 {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("can't parse line: {:?}", line),
-                ));
+    /// Parse every file an Include directive matches.
+    fn include_files(&mut self, directive: &Directive) -> io::Result<()> {
+        for pattern in &directive.args {
+            let pattern = expand_tilde(pattern);
+            let pattern = if pattern.is_absolute() {
+                pattern
             } else {
-                match line[0].to_lowercase().as_ref() {
-                    "host" => {
-                        let parsed = &line[1];
-                        // skip any Host patterns
-                        if parsed.contains('*')
-                            || parsed.contains('!')
-                            || parsed.contains('?')
-                            || parsed.contains(',')
-                            || parsed.contains(' ')
-                        {
-                            stanza.clear();
-                        } else {
-                            stanza = parsed.clone();
-                            // by default we assume host patterns are
-                            // actual hostnames
-                            map.insert(stanza.clone(), stanza.clone());
-                        }
-                    }
-                    "hostname" => {
-                        if !stanza.is_empty() {
-                            map.insert(stanza.clone(), line[1].clone());
-                            stanza.clear();
-                        }
-                    }
-                    _ => {}
+                self.ssh_dir.join(pattern)
+            };
+            for path in glob(&pattern) {
+                match self.parse_file(&path) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    res => res?,
                 }
-                line.clear();
             }
-        } else if (c == ' ' || c == '=') && token.is_empty() {
-            // skip = and whitespace at start of value, key = value format
+        }
+        Ok(())
+    }
+}
+
+/// $HOME, which we need to find ~/.ssh
+fn home_dir() -> PathBuf {
+    PathBuf::from(env::var("HOME").expect("$HOME must be set"))
+}
+
+/// Turns ~/.ssh/config into /home/you/.ssh/config
+fn expand_tilde(path: &str) -> PathBuf {
+    if path == "~" {
+        home_dir()
+    } else if let Some(rest) = path.strip_prefix("~/") {
+        home_dir().join(rest)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Expand `*` and `?` wildcards in a path into the files they match,
+/// sorted like glob(3). Wildcards don't match dotfiles unless the
+/// pattern itself starts with a dot. Paths without wildcards are
+/// returned as-is, whether they exist or not.
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+    for part in pattern.components() {
+        let part = part.as_os_str();
+        let part_str = part.to_string_lossy();
+        if !part_str.contains('*') && !part_str.contains('?') {
+            paths.iter_mut().for_each(|p| p.push(part));
             continue;
+        }
+
+        let mut matches = vec![];
+        for dir in &paths {
+            let entries = match fs::read_dir(if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            let mut names = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .filter(|name| part_str.starts_with('.') || !name.starts_with('.'))
+                .filter(|name| wildcard_match(&part_str, name))
+                .collect::<Vec<_>>();
+            names.sort();
+            matches.extend(names.into_iter().map(|name| dir.join(name)));
+        }
+        paths = matches;
+    }
+    paths
+}
+
+/// Match a string against a pattern containing `*` (any number of
+/// chars) and `?` (exactly one char) wildcards.
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let s = s.chars().collect::<Vec<_>>();
+    let (mut p, mut i) = (0, 0);
+    let mut star = None; // last * we saw, and where it started matching
+
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            // backtrack: let the last * eat one more char
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
         } else {
-            // regular char
-            token.push(c);
+            return false;
         }
     }
 
-    Ok(map)
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 #[cfg(test)]
@@ -107,27 +1128,477 @@ mod tests {
     #[test]
     fn test_config() {
         let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
-        assert_eq!(11, config.len());
+        assert_eq!(14, config.len());
 
         assert_eq!(
             config.keys().cloned().collect::<Vec<_>>(),
             vec![
+                "devserver",
+                "otherserver",
                 "homework-server",
                 "nixcraft",
                 "docker1",
                 "nas01",
                 "docker2",
                 "docker3",
-                "devserver",
                 "ec2-some-long-name.amazon.probably.com",
                 "ec2-some-long-namer.amazon.probably.com",
+                "uk.gw.lan",
+                "uk.lan",
                 "torrentz-server",
                 "midi-files.com",
             ]
         );
-        assert_eq!("torrentz-r-us.com", config.get("torrentz-server").unwrap());
-        assert_eq!("docker3.mycloud.net", config.get("docker3").unwrap());
-        assert_eq!("192.168.1.100", config.get("nas01").unwrap());
-        assert_eq!("midi-files.com", config.get("midi-files.com").unwrap());
+        let hostname = |name| config.get(name).unwrap().hostname();
+        assert_eq!("torrentz-r-us.com", hostname("torrentz-server"));
+        assert_eq!("docker3.mycloud.net", hostname("docker3"));
+        assert_eq!("192.168.1.100", hostname("nas01"));
+        assert_eq!("midi-files.com", hostname("midi-files.com"));
+    }
+
+    #[test]
+    fn test_host_entry() {
+        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
+
+        let nixcraft = config.get("nixcraft").unwrap();
+        assert_eq!(Some("nixcraft"), nixcraft.user.as_deref());
+        assert_eq!(Some(4242), nixcraft.port);
+        assert_eq!(
+            vec!["/nfs/shared/users/nixcraft/keys/server1/id_rsa"],
+            nixcraft.identity_files
+        );
+
+        let nas01 = config.get("nas01").unwrap();
+        assert_eq!(Some("root"), nas01.user.as_deref());
+        assert_eq!(vec!["~/.ssh/nas01.key"], nas01.identity_files);
+        assert_eq!(Some(22), nas01.port); // from Host *
+
+        let docker1 = config.get("docker1").unwrap();
+        assert_eq!(
+            vec![Forward {
+                listen: "8080".into(),
+                target: Some("localhost:80".into())
+            }],
+            docker1.local_forwards
+        );
+        assert_eq!(
+            vec![Forward {
+                listen: "1080".into(),
+                target: None
+            }],
+            docker1.remote_forwards
+        );
+        assert_eq!(Some("gateway"), docker1.proxy_jump.as_deref());
+        assert_eq!(Some("yes"), docker1.option("compression"));
+    }
+
+    #[test]
+    fn test_first_value_wins() {
+        let config = parse_ssh_config(
+            "Host box\n  HOSTNAME first.com\n  hostname second.com\n  User a\n\
+             Host box\n  User b\n  IdentityFile one\n  IdentityFile two\n",
+        )
+        .expect("failed to parse config");
+        let entry = config.get("box").unwrap();
+        assert_eq!("first.com", entry.hostname());
+        assert_eq!(Some("a"), entry.user.as_deref());
+        assert_eq!(vec!["one", "two"], entry.identity_files);
+    }
+
+    #[test]
+    fn test_include() {
+        let mut parser = Parser::new(PathBuf::from("./tests"));
+        parser
+            .parse_file(Path::new("./tests/include_config"))
+            .expect("failed to parse config");
+        let config = parser.config.hosts();
+
+        assert_eq!(
+            config.keys().cloned().collect::<Vec<_>>(),
+            vec!["first", "web1", "db1", "web2", "last"]
+        );
+        assert_eq!("db1.example.com", config.get("db1").unwrap().hostname());
+
+        let source = config.get("db1").unwrap().source.clone().unwrap();
+        assert!(source
+            .path
+            .unwrap()
+            .ends_with("tests/include.d/nested/db.conf"));
+        assert_eq!(1, source.line);
+        let source = config.get("last").unwrap().source.clone().unwrap();
+        assert!(source.path.unwrap().ends_with("tests/include_config"));
+        assert_eq!(6, source.line);
+        assert_eq!("last.example.com", config.get("last").unwrap().hostname());
+    }
+
+    #[test]
+    fn test_conditional_include() {
+        let mut parser = Parser::new(PathBuf::from("./tests"));
+        parser
+            .parse_file(Path::new("./tests/conditional_include_config"))
+            .expect("failed to parse config");
+        let config = parser.config.hosts();
+
+        assert_eq!(
+            config.keys().cloned().collect::<Vec<_>>(),
+            vec!["bastion", "db.corp", "plain"]
+        );
+        // ssh never applies `Host stray` under `Host *.corp`, so it
+        // isn't a host we can launch
+        assert!(!parser.config.aliases.contains_key("stray"));
+        let db = &config["db.corp"];
+        assert_eq!("10.0.0.5", db.hostname());
+        assert_eq!(Some("corpuser"), db.user.as_deref());
+        assert_eq!(Some(2222), db.port);
+        // the included stanzas don't leak out of `Host *.corp`
+        for alias in &["bastion", "plain"] {
+            assert_eq!(None, config[*alias].port, "{}", alias);
+            assert_eq!(None, config[*alias].user, "{}", alias);
+        }
+        assert_eq!("plain.example.com", config["plain"].hostname());
+    }
+
+    #[test]
+    fn test_wildcard_stanzas() {
+        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
+
+        // Host docker*
+        let docker3 = config.get("docker3").unwrap();
+        assert_eq!(vec!["~/.ssh/docker.key"], docker3.identity_files);
+        // Host *, but nixcraft's own Port comes first
+        assert_eq!(Some(22), docker3.port);
+        assert_eq!(Some(4242), config.get("nixcraft").unwrap().port);
+        // User from Host * doesn't override an earlier one
+        assert_eq!(Some("nixcraft"), docker3.user.as_deref());
+        assert_eq!(Some("root"), config.get("nas01").unwrap().user.as_deref());
+        assert_eq!(Some("60"), docker3.option("serveraliveinterval"));
+    }
+
+    #[test]
+    fn test_multiple_patterns() {
+        let config = load_ssh_config("./tests/test_config").expect("failed to parse config");
+
+        // the first Host line mentioning devserver wins
+        assert_eq!("devserver", config.get("devserver").unwrap().hostname());
+        assert_eq!("devserver", config.get("otherserver").unwrap().hostname());
+
+        for name in &["uk.gw.lan", "uk.lan"] {
+            let entry = config.get(*name).unwrap();
+            assert_eq!("192.168.0.251", entry.hostname());
+            assert_eq!(Some("nixcraft"), entry.user.as_deref());
+            assert_eq!(
+                Some("ssh nixcraft@gateway.uk.cyberciti.biz nc %h %p 2> /dev/null"),
+                entry.proxy_command.as_deref()
+            );
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let config = parse_ssh_config(
+            "# my ssh config\n\
+             \n\
+             # primary postgres\n\
+             Host db1 db2\n  User postgres\n  # on call: ops\n\
+             \n\
+             Host web\n  # behind the lb\n\
+             # trailing\n\
+             \n\
+             # every host\n\
+             Host *\n  User nobody\n",
+        )
+        .expect("failed to parse config");
+
+        let comments = |alias: &str| config.get(alias).unwrap().comments.clone();
+        assert_eq!(vec!["primary postgres", "on call: ops"], comments("db1"));
+        assert_eq!(comments("db1"), comments("db2"));
+        assert_eq!(vec!["behind the lb", "trailing"], comments("web"));
+    }
+
+    #[test]
+    fn test_annotations() {
+        let config = parse_ssh_config(
+            "# shy: tags=prod,db group=\"EU Cluster\" desc=\"primary postgres\"\n\
+             # the main one\n\
+             Host db1 db2\n  # shy: tags=db,backup group=ignored\n  User postgres\n\
+             Host db2\n  # shy: tags=replica desc='read only' color=red\n  Port 2222\n\
+             Host web\n  # shy: desc=\"unterminated\n",
+        )
+        .expect("failed to parse config");
+
+        let db1 = &config["db1"];
+        assert_eq!(vec!["prod", "db", "backup"], db1.tags);
+        assert_eq!(Some("EU Cluster"), db1.group.as_deref());
+        assert_eq!(Some("primary postgres"), db1.desc.as_deref());
+        assert_eq!(vec!["the main one"], db1.comments);
+
+        let db2 = &config["db2"];
+        assert_eq!(vec!["prod", "db", "backup", "replica"], db2.tags);
+        assert_eq!(Some("primary postgres"), db2.desc.as_deref());
+
+        // a bad annotation is just a comment
+        let web = &config["web"];
+        assert_eq!(None, web.desc);
+        assert_eq!(vec!["shy: desc=\"unterminated"], web.comments);
+    }
+
+    #[test]
+    fn test_negated_patterns() {
+        let config = parse_ssh_config(
+            "User global\n\
+             Host web1\n\
+             Host web2\n\
+             Host web* !web2\n  User deploy\n  Port 2222\n\
+             Host *\n  User nobody\n",
+        )
+        .expect("failed to parse config");
+
+        let web1 = config.get("web1").unwrap();
+        assert_eq!(Some("global"), web1.user.as_deref());
+        assert_eq!(Some(2222), web1.port);
+
+        let web2 = config.get("web2").unwrap();
+        assert_eq!(Some("global"), web2.user.as_deref());
+        assert_eq!(None, web2.port);
+    }
+
+    #[test]
+    fn test_match_blocks() {
+        let config = load_ssh_config("./tests/match_config").expect("failed to parse config");
+
+        // Match host checks the HostName, not the alias
+        let db = config.get("db").unwrap();
+        assert_eq!(Some("postgres"), db.user.as_deref());
+        assert_eq!(Some(5432), db.port);
+        // options after a Match don't leak into the Host before it
+        let web = config.get("web").unwrap();
+        assert_eq!(Some("deploy"), web.user.as_deref());
+        assert_eq!(None, web.port);
+        // Match originalhost + user, using the User we've seen so far
+        assert_eq!(vec!["~/.ssh/deploy.key"], web.identity_files);
+        // `final` blocks apply on the second pass
+        assert_eq!(Some("yes"), web.option("compression"));
+        // Match !all never applies
+        assert_eq!(None, web.option("forwardagent"));
+    }
+
+    #[test]
+    fn test_match_exec() {
+        let config = Config::parse(
+            "Host box\n\
+             Match exec \"test %n = box\"\n  User exec\n\
+             Match all\n  User fallback\n",
+        )
+        .expect("failed to parse config");
+
+        let entry = config.resolve("box");
+        assert_eq!(Some("fallback"), entry.user.as_deref());
+
+        let options = Options {
+            match_exec: true,
+            ..Default::default()
+        };
+        let entry = config.resolve_with("box", &options);
+        assert_eq!(Some("exec"), entry.user.as_deref());
+    }
+
+    #[test]
+    fn test_expand_tokens() {
+        let mut entry = HostEntry::new("box");
+        entry.set("HostName", "box.lan").unwrap();
+        entry.set("User", "me").unwrap();
+        assert_eq!(
+            "test box.lan:22 = box as me 100%",
+            expand_tokens("test %h:%p = %n as %r 100%%", &entry)
+        );
+    }
+
+    #[test]
+    fn test_match_criteria() {
+        let directive = Directive::new("Match", "!host *.lan,!nas.lan user root final").unwrap();
+        let criteria = Criterion::parse_all(&directive).expect("failed to parse criteria");
+        assert_eq!(
+            vec![
+                Criterion {
+                    kind: CriterionKind::Host(vec![
+                        Pattern::new("*.lan"),
+                        Pattern::new("!nas.lan")
+                    ]),
+                    negated: true,
+                },
+                Criterion {
+                    kind: CriterionKind::User(vec![Pattern::new("root")]),
+                    negated: false,
+                },
+                Criterion {
+                    kind: CriterionKind::Final,
+                    negated: false,
+                },
+            ],
+            criteria
+        );
+        let directive = Directive::new("Match", "user").unwrap();
+        assert_eq!(
+            ParseErrorKind::MissingCriterionArg("user".into()),
+            Criterion::parse_all(&directive).unwrap_err().kind
+        );
+    }
+
+    #[test]
+    fn test_quoted_values() {
+        let config = Config::parse(
+            "Host box\n\
+             \tIdentityFile \"/path/with spaces/key\"\n\
+             \tLocalForward 8080 localhost:80\n\
+             \tSendEnv LANG LC_*\n\
+             \tSendEnv=EDITOR\n\
+             \tUser=\"me\" # trailing comment\n\
+             \tPort = 2222\n\
+             \tProxyCommand ssh -W \"%h:%p\" gateway\n",
+        )
+        .expect("failed to parse config");
+
+        let entry = config.resolve("box");
+        assert_eq!(vec!["/path/with spaces/key"], entry.identity_files);
+        assert_eq!(
+            vec![Forward {
+                listen: "8080".into(),
+                target: Some("localhost:80".into())
+            }],
+            entry.local_forwards
+        );
+        assert_eq!(
+            Some(&vec!["LANG".to_string(), "LC_*".into(), "EDITOR".into()]),
+            entry.options.get("sendenv")
+        );
+        assert_eq!(Some("me"), entry.user.as_deref());
+        assert_eq!(Some(2222), entry.port);
+        assert_eq!(
+            Some("ssh -W \"%h:%p\" gateway"),
+            entry.proxy_command.as_deref()
+        );
+    }
+
+    #[test]
+    fn test_split_args() {
+        let split = |s| {
+            split_args(s, 1).map(|args| args.into_iter().map(|(_, arg)| arg).collect::<Vec<_>>())
+        };
+        assert_eq!(vec!["a", "b"], split("  a\tb  ").unwrap());
+        assert_eq!(vec!["a b", "c"], split("\"a b\" c").unwrap());
+        assert_eq!(vec!["it's"], split("\"it's\"").unwrap());
+        assert_eq!(vec!["a b"], split("a\\ b").unwrap());
+        assert_eq!(vec!["a"], split("a #b c").unwrap());
+        assert_eq!(Vec::<String>::new(), split("").unwrap());
+        assert_eq!(
+            Err((3, ParseErrorKind::UnterminatedQuote)),
+            split("a \"oops")
+        );
+        assert_eq!(
+            vec![(1, "a".to_string()), (3, "b c".into()), (9, "d".into())],
+            split_args("a \"b c\" d", 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |config| {
+            let err = Config::parse(config).unwrap_err();
+            err.get_ref()
+                .and_then(|e| e.downcast_ref::<ParseError>())
+                .cloned()
+                .expect("expected a ParseError")
+        };
+
+        let e = err("Host box\n\tIdentityFile \"/oops\n");
+        assert_eq!(ParseErrorKind::UnterminatedQuote, e.kind);
+        assert_eq!((2, 15), (e.line, e.column));
+        assert_eq!("\tIdentityFile \"/oops", e.text);
+        assert_eq!("2:15: unterminated quote", e.to_string());
+
+        let e = err("Host box\n  Port = ssh\n");
+        assert_eq!(ParseErrorKind::InvalidPort("ssh".into()), e.kind);
+        assert_eq!((2, 10), (e.line, e.column));
+
+        let e = err("\nHost\n");
+        assert_eq!(ParseErrorKind::MissingValue("host".into()), e.kind);
+        assert_eq!((2, 5), (e.line, e.column));
+
+        let e = err("Match host a user\n");
+        assert_eq!(ParseErrorKind::MissingCriterionArg("user".into()), e.kind);
+        assert_eq!((1, 14), (e.line, e.column));
+    }
+
+    #[test]
+    fn test_lenient() {
+        let config = "Host ok1\n  User a\n\
+                      Host\n  User leaked\n\
+                      Host ok2\n  Port nope\n  IdentityFile \"/oops\n  User b\n\
+                      Match user\n  User leaked\n";
+        assert!(parse_ssh_config(config).is_err());
+
+        let options = Options {
+            lenient: true,
+            ..Default::default()
+        };
+        let config = Config::parse_with(config, &options).expect("lenient mode failed");
+        assert_eq!(
+            vec!["ok1", "ok2"],
+            config.aliases.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![3, 6, 7, 9],
+            config.warnings.iter().map(|w| w.line).collect::<Vec<_>>()
+        );
+
+        let hosts = config.hosts();
+        assert_eq!(Some("a"), hosts.get("ok1").unwrap().user.as_deref());
+        let ok2 = hosts.get("ok2").unwrap();
+        assert_eq!(Some("b"), ok2.user.as_deref());
+        assert_eq!(None, ok2.port);
+        assert!(ok2.identity_files.is_empty());
+    }
+
+    #[test]
+    fn test_lenient_include() {
+        let mut parser = Parser::new(PathBuf::from("./tests"));
+        parser.lenient = true;
+        parser
+            .parse_file(Path::new("./tests/bad_include_config"))
+            .expect("lenient mode failed");
+        assert_eq!(
+            vec!["fine", "broken"],
+            parser.config.aliases.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(1, parser.config.warnings.len());
+        assert!(parser.config.warnings[0]
+            .path
+            .as_ref()
+            .unwrap()
+            .ends_with("tests/bad.d/bad.conf"));
+    }
+
+    #[test]
+    fn test_parse_error_path() {
+        let mut parser = Parser::new(PathBuf::from("./tests"));
+        let err = parser
+            .parse_file(Path::new("./tests/bad_include_config"))
+            .unwrap_err();
+        let err = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<ParseError>())
+            .expect("expected a ParseError");
+        assert!(err.path.as_ref().unwrap().ends_with("tests/bad.d/bad.conf"));
+        assert_eq!((2, 12), (err.line, err.column));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*.conf", "web.conf"));
+        assert!(wildcard_match("docker?", "docker1"));
+        assert!(wildcard_match("d*r*1", "docker1"));
+        assert!(!wildcard_match("docker?", "docker10"));
+        assert!(!wildcard_match("*.conf", "web.conf.bak"));
     }
 }
@@ -0,0 +1,440 @@
+//! The colors shy draws with. Pick a built-in theme, then change any
+//! part of it in the settings file:
+//!
+//! ```toml
+//! theme = "light"
+//!
+//! [colors]
+//! selection = "bold #ff8700"
+//! status = "black on 214"
+//! ```
+//!
+//! A style is any of `bold`, `dim`, `italic`, `underline` and
+//! `reverse`, a foreground color, and `on` a background color. Colors
+//! are a name like `red` or `bright-red`, a number from the 256 color
+//! palette, or `#rrggbb`.
+
+use {
+    crate::color::{fixed_to_basic, rgb_to_fixed, Depth},
+    std::{fmt, str::FromStr},
+};
+
+/// A terminal color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    /// Whatever the terminal uses when nothing is set.
+    Default,
+    /// One of the 16 basic colors. 0-7 are the normal ones, 8-15 the
+    /// bright ones.
+    Basic(u8),
+    /// A color from the 256 color palette.
+    Fixed(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+/// The basic colors, in SGR order.
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Color {
+    /// The SGR parameters for this color, as the foreground or
+    /// background.
+    fn code(self, background: bool) -> String {
+        let offset = if background { 10 } else { 0 };
+        match self {
+            Color::Default => format!("{}", 39 + offset),
+            Color::Basic(n) if n < 8 => format!("{}", 30 + offset + n),
+            Color::Basic(n) => format!("{}", 90 + offset + n - 8),
+            Color::Fixed(n) => format!("{};5;{}", 38 + offset, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", 38 + offset, r, g, b),
+        }
+    }
+
+    /// The closest color the terminal can show.
+    fn limit(self, depth: Depth) -> Color {
+        match (self, depth) {
+            (Color::Rgb(r, g, b), Depth::Fixed) => Color::Fixed(rgb_to_fixed(r, g, b)),
+            (Color::Rgb(r, g, b), Depth::Basic) => {
+                Color::Basic(fixed_to_basic(rgb_to_fixed(r, g, b)))
+            }
+            (Color::Fixed(n), Depth::Basic) => Color::Basic(fixed_to_basic(n)),
+            (color, _) => color,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Color, String> {
+        let lower = s.to_lowercase();
+        if let Some(hex) = lower.strip_prefix('#') {
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb(r, g, b)),
+                _ => Err(format!("bad color: {} (try #rrggbb)", s)),
+            };
+        }
+        if let Ok(n) = lower.parse() {
+            return Ok(Color::Fixed(n));
+        }
+        let (bright, name) = match lower.strip_prefix("bright-") {
+            Some(name) => (true, name),
+            None => (false, lower.as_str()),
+        };
+        match name {
+            "default" if !bright => Ok(Color::Default),
+            "grey" | "gray" if !bright => Ok(Color::Basic(8)),
+            _ => match NAMES.iter().position(|n| *n == name) {
+                Some(n) => Ok(Color::Basic(n as u8 + if bright { 8 } else { 0 })),
+                None => Err(format!("unknown color: {}", s)),
+            },
+        }
+    }
+}
+
+/// How one part of the screen looks.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    /// `text` in this style, followed by a reset.
+    pub fn paint(&self, text: &str) -> String {
+        format!("{}{}{}", self, text, crate::color::Reset)
+    }
+
+    /// This style, but bold.
+    pub fn bold(self) -> Style {
+        Style { bold: true, ..self }
+    }
+
+    /// This style with colors the terminal can show.
+    fn limit(self, depth: Depth) -> Style {
+        Style {
+            fg: self.fg.map(|c| c.limit(depth)),
+            bg: self.bg.map(|c| c.limit(depth)),
+            ..self
+        }
+    }
+}
+
+/// Parse a style like `bold yellow on magenta`. An empty style leaves
+/// the terminal's colors alone.
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Style, String> {
+        let mut style = Style::default();
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            match word.to_lowercase().as_ref() {
+                "bold" => style.bold = true,
+                "dim" => style.dim = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                "on" => match words.next() {
+                    Some(color) => style.bg = Some(color.parse()?),
+                    None => return Err(format!("missing color after `on` in: {}", s)),
+                },
+                _ => style.fg = Some(word.parse()?),
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// The escape code that turns this style on.
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut codes = vec![];
+        let attrs = [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.reverse, "7"),
+        ];
+        codes.extend(
+            attrs
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, c)| c.to_string()),
+        );
+        codes.extend(self.fg.map(|c| c.code(false)));
+        codes.extend(self.bg.map(|c| c.code(true)));
+        if codes.is_empty() {
+            Ok(())
+        } else {
+            write!(f, "\x1b[{}m", codes.join(";"))
+        }
+    }
+}
+
+/// A style for each part of the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Host names.
+    pub host: Style,
+    /// The selected host, group, or menu item.
+    pub selection: Style,
+    /// The status bar.
+    pub status: Style,
+    /// The search bar with nothing typed, and the action prompts.
+    pub prompt: Style,
+    /// The search bar when something matches.
+    pub found: Style,
+    /// The search bar when nothing matches.
+    pub missed: Style,
+    /// The letters that matched the search, in the host list.
+    pub matched: Style,
+    /// Group headers.
+    pub header: Style,
+    /// Matched fields and preview pane labels.
+    pub dim: Style,
+    /// Where each config warning is.
+    pub warning: Style,
+}
+
+impl Theme {
+    /// The built-in themes' names.
+    pub const NAMES: [&'static str; 5] = ["default", "light", "high-contrast", "nord", "mono"];
+
+    /// Every slot's name, in the order `named` lists their styles.
+    pub const SLOTS: [&'static str; 10] = [
+        "host",
+        "selection",
+        "status",
+        "prompt",
+        "found",
+        "missed",
+        "matched",
+        "header",
+        "dim",
+        "warning",
+    ];
+
+    /// A built-in theme.
+    pub fn named(name: &str) -> Option<Theme> {
+        let slots = match name {
+            "default" => [
+                "bright-white",
+                "bright-yellow bold",
+                "bright-yellow on magenta",
+                "black on white",
+                "black on green",
+                "bright-white on red",
+                "underline",
+                "bright-cyan bold",
+                "grey",
+                "bright-yellow",
+            ],
+            "light" => [
+                "default",
+                "blue bold",
+                "bright-white on blue",
+                "black on 254",
+                "black on 151",
+                "black on 217",
+                "underline",
+                "magenta bold",
+                "244",
+                "red",
+            ],
+            "high-contrast" => [
+                "bright-white",
+                "black bold on bright-yellow",
+                "black bold on bright-white",
+                "black on bright-white",
+                "black on bright-green",
+                "bright-white bold on red",
+                "bold underline",
+                "bright-cyan bold underline",
+                "white",
+                "bright-yellow bold",
+            ],
+            "nord" => [
+                "#d8dee9",
+                "#88c0d0 bold",
+                "#eceff4 on #5e81ac",
+                "#2e3440 on #d8dee9",
+                "#2e3440 on #a3be8c",
+                "#2e3440 on #bf616a",
+                "underline",
+                "#b48ead bold",
+                "#616e88",
+                "#ebcb8b",
+            ],
+            // no colors, so the selection and status bar are reversed
+            "mono" => [
+                "",
+                "bold reverse",
+                "reverse",
+                "reverse",
+                "reverse",
+                "reverse",
+                "underline",
+                "bold",
+                "",
+                "bold",
+            ],
+            _ => return None,
+        };
+        let mut theme = Theme::plain();
+        for (slot, style) in Theme::SLOTS.iter().zip(slots.iter()) {
+            theme.set(slot, style).expect("bad built-in theme");
+        }
+        Some(theme)
+    }
+
+    /// No colors at all.
+    pub fn plain() -> Theme {
+        let none = Style::default();
+        Theme {
+            host: none,
+            selection: none,
+            status: none,
+            prompt: none,
+            found: none,
+            missed: none,
+            matched: none,
+            header: none,
+            dim: none,
+            warning: none,
+        }
+    }
+
+    /// This theme, for a terminal with `depth` colors. Without colors
+    /// it's the `mono` theme, so nothing relies on color alone.
+    pub fn limit(&self, depth: Depth) -> Theme {
+        if depth == Depth::Mono {
+            return Theme::named("mono").expect("no mono theme");
+        }
+        Theme {
+            host: self.host.limit(depth),
+            selection: self.selection.limit(depth),
+            status: self.status.limit(depth),
+            prompt: self.prompt.limit(depth),
+            found: self.found.limit(depth),
+            missed: self.missed.limit(depth),
+            matched: self.matched.limit(depth),
+            header: self.header.limit(depth),
+            dim: self.dim.limit(depth),
+            warning: self.warning.limit(depth),
+        }
+    }
+
+    /// Set one slot's style, both by name, like the settings file does.
+    pub fn set(&mut self, slot: &str, style: &str) -> Result<(), String> {
+        let style = style.parse()?;
+        match slot {
+            "host" => self.host = style,
+            "selection" => self.selection = style,
+            "status" => self.status = style,
+            "prompt" => self.prompt = style,
+            "found" => self.found = style,
+            "missed" => self.missed = style,
+            "matched" => self.matched = style,
+            "header" => self.header = style,
+            "dim" => self.dim = style,
+            "warning" => self.warning = style,
+            _ => {
+                return Err(format!(
+                    "unknown theme slot: {} (try {})",
+                    slot,
+                    Theme::SLOTS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::named("default").expect("no default theme")
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Theme, String> {
+        Theme::named(name)
+            .ok_or_else(|| format!("unknown theme: {} (try {})", name, Theme::NAMES.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(Ok(Color::Basic(1)), "red".parse());
+        assert_eq!(Ok(Color::Basic(11)), "Bright-Yellow".parse());
+        assert_eq!(Ok(Color::Basic(8)), "grey".parse());
+        assert_eq!(Ok(Color::Fixed(214)), "214".parse());
+        assert_eq!(Ok(Color::Rgb(255, 135, 0)), "#FF8700".parse());
+        assert!("256".parse::<Color>().is_err());
+        assert!("#ff87".parse::<Color>().is_err());
+        assert!("bright-default".parse::<Color>().is_err());
+        assert!("mauve".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn test_styles() {
+        let style = |s: &str| s.parse::<Style>().unwrap().to_string();
+        assert_eq!("", style(""));
+        assert_eq!("\x1b[1;93;45m", style("bright-yellow bold on magenta"));
+        assert_eq!("\x1b[4;38;5;214m", style("underline 214"));
+        assert_eq!(
+            "\x1b[38;2;46;52;64;48;2;216;222;233m",
+            style("#2e3440 on #d8dee9")
+        );
+        assert_eq!("\x1b[39;100m", style("default on grey"));
+        assert!("yellow on".parse::<Style>().is_err());
+        assert!("blink".parse::<Style>().is_err());
+    }
+
+    #[test]
+    fn test_themes() {
+        for name in &Theme::NAMES {
+            assert!(Theme::named(name).is_some(), "{}", name);
+        }
+        let mut theme = Theme::default();
+        assert_eq!("\x1b[1;93m", theme.selection.to_string());
+        theme.set("selection", "blue").unwrap();
+        assert_eq!("\x1b[34mweb\x1b[0m", theme.selection.paint("web"));
+        assert!(theme.set("cursor", "blue").is_err());
+        assert!("solarized".parse::<Theme>().is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        let nord = Theme::named("nord").unwrap();
+        assert_eq!(nord, nord.limit(Depth::Rgb));
+        assert_eq!(
+            Some(Color::Fixed(110)),
+            nord.limit(Depth::Fixed).selection.fg
+        );
+        assert_eq!(Some(Color::Basic(6)), nord.limit(Depth::Basic).selection.fg);
+        assert_eq!(Theme::named("mono"), Some(nord.limit(Depth::Mono)));
+        let light = Theme::named("light").unwrap().limit(Depth::Basic);
+        assert_eq!(Some(Color::Basic(15)), light.prompt.bg);
+        assert_eq!(Some(Color::Default), light.host.fg);
+    }
+}
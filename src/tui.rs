@@ -1,36 +1,83 @@
 use {
     crate::{
-        color,
-        ssh_config::{load_ssh_config, HostMap},
+        history::Frecency,
+        keymap::{Command, Keymap},
+        launch::{osc52, Action, Launch},
+        preview,
+        query::Query,
+        search::{self, Field, Match},
+        settings::Settings,
+        ssh_config::{HostEntry, HostMap, ParseError},
+        theme::{Style, Theme},
     },
     flume::{unbounded, Receiver, Selector},
-    fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher},
+    fuzzy_matcher::skim::SkimMatcherV2,
     std::{
         borrow::Cow,
+        cmp::Reverse,
+        collections::HashSet,
         io::{self, Stdout, Write},
+        str::FromStr,
         thread,
+        time::{Duration, Instant},
     },
     termion::{
         clear::{All as ClearAll, CurrentLine as ClearLine},
         cursor::{Goto, Hide as HideCursor, Show as ShowCursor},
-        event::Key,
-        input::TermRead,
+        event::{self, Key, MouseButton, MouseEvent},
+        input::{MouseTerminal, TermRead},
         raw::{IntoRawMode, RawTerminal},
         screen::{ToAlternateScreen, ToMainScreen},
         terminal_size,
     },
 };
 
+/// How many columns the preview pane takes, border included.
+const PREVIEW_WIDTH: usize = 46;
+
+/// The preview pane hides itself unless the list gets at least this
+/// many columns.
+const MIN_LIST_WIDTH: usize = 40;
+
+/// Two clicks on the same host this close together connect to it.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// How many rows one notch of the scroll wheel moves.
+const SCROLL_ROWS: usize = 3;
+
 /// App state.
 pub struct TUI {
     pub mode: Mode,
+    /// Extra arguments passed to ssh after the host, shown in the
+    /// status bar.
+    pub ssh_args: Vec<String>,
+    /// How often and how recently each host was launched.
+    pub frecency: Frecency,
+    /// Show the selected host's details next to the list, when there's
+    /// room.
+    pub preview: bool,
     status: SearchStatus,
     input: String,
+    query: Query, // the parsed input
     selected: usize,
     offset: usize,
     size: (u16, u16),
     hosts: HostMap,
-    stdout: RawTerminal<Stdout>,
+    rows: Vec<Row>, // in display order
+    sort: Sort,
+    view: View,
+    jump: usize, // rows PageUp and PageDown move
+    keymap: Keymap,
+    theme: Theme,
+    collapsed: HashSet<String>, // groups whose hosts are hidden
+    tag: Option<String>,        // only show hosts with this tag
+    warnings: Vec<ParseError>,
+    warnings_offset: usize,
+    menu_selected: usize,
+    prompt: String,
+    message: Option<String>,
+    last_click: Option<(usize, Instant)>, // row and time, for double clicks
+    stdout: Option<MouseTerminal<RawTerminal<Stdout>>>, // None in tests
     matcher: SkimMatcherV2,
 }
 
@@ -39,8 +86,84 @@ pub struct TUI {
 pub enum Mode {
     Search,
     Nav,
+    Warnings,
+    /// The action menu for the selected host.
+    Actions,
+    /// Asking for the input an action needs, like a file to copy.
+    Prompt(Action),
     Quit,
-    Launch(String),
+    Launch(Launch),
+}
+
+/// A line in the host list.
+#[derive(Debug, Clone, PartialEq)]
+enum Row {
+    /// The name of a group. Hosts under it are hidden while it's
+    /// collapsed.
+    Header(String),
+    /// An index into `hosts`.
+    Host(usize),
+}
+
+/// The order hosts are listed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    /// The order they're in the config.
+    Config,
+    /// Most used and most recent first.
+    Frecency,
+}
+
+impl FromStr for Sort {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Sort> {
+        match s {
+            "config" => Ok(Sort::Config),
+            "frecency" => Ok(Sort::Frecency),
+            _ => Err(io::Error::other(format!(
+                "unknown sort: {} (try config or frecency)",
+                s
+            ))),
+        }
+    }
+}
+
+/// What hosts are listed under.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum View {
+    /// One list, no headers.
+    Flat,
+    /// Headers for `# shy: group=...` comments.
+    Groups,
+    /// Headers for each config file, including Included ones.
+    Files,
+}
+
+impl FromStr for View {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<View> {
+        match s {
+            "flat" => Ok(View::Flat),
+            "groups" => Ok(View::Groups),
+            "files" => Ok(View::Files),
+            _ => Err(io::Error::other(format!(
+                "unknown view: {} (try flat, groups, or files)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Something for the TUI to respond to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Key(Key),
+    Mouse(MouseEvent),
+    /// The terminal changed size. It isn't a key, so it can't be
+    /// rebound and works in every mode.
+    Resize,
 }
 
 /// Was the input search successful?
@@ -52,59 +175,232 @@ pub enum SearchStatus {
 }
 
 impl TUI {
-    /// Create a new main view and sets up the terminal.
-    pub fn new(config_path: &str) -> io::Result<TUI> {
-        Ok(TUI {
-            mode: Mode::Nav,
+    /// Create a new main view and sets up the terminal. Any warnings
+    /// from parsing the config can be viewed from Nav mode.
+    pub fn new(hosts: HostMap, warnings: Vec<ParseError>, settings: &Settings) -> io::Result<TUI> {
+        let size = terminal_size()?;
+        let stdout = Self::setup_terminal()?;
+        Ok(Self::with_terminal(
+            hosts,
+            warnings,
+            settings,
+            size,
+            Some(stdout),
+        ))
+    }
+
+    /// Create the main view for a terminal of the given size.
+    fn with_terminal(
+        hosts: HostMap,
+        warnings: Vec<ParseError>,
+        settings: &Settings,
+        size: (u16, u16),
+        stdout: Option<MouseTerminal<RawTerminal<Stdout>>>,
+    ) -> TUI {
+        let mut tui = TUI {
+            mode: if settings.search {
+                Mode::Search
+            } else {
+                Mode::Nav
+            },
+            ssh_args: vec![],
+            frecency: Frecency::new(),
+            preview: settings.preview,
             status: SearchStatus::Blank,
             input: String::new(),
+            query: Query::default(),
             selected: 0,
             offset: 0,
-            size: terminal_size()?,
-            hosts: load_ssh_config(config_path)?,
-            stdout: Self::setup_terminal()?,
+            size,
+            rows: (0..hosts.len()).map(Row::Host).collect(),
+            sort: settings.sort,
+            view: settings.view,
+            jump: settings.jump,
+            keymap: settings.keymap.clone(),
+            theme: settings.styles().limit(settings.color.depth()),
+            collapsed: HashSet::new(),
+            tag: None,
+            hosts,
+            warnings,
+            warnings_offset: 0,
+            menu_selected: 0,
+            prompt: String::new(),
+            message: None,
+            last_click: None,
+            stdout,
             matcher: Default::default(),
-        })
+        };
+        tui.sort_by(settings.sort);
+        tui
+    }
+
+    /// List hosts in a different order, starting from the top.
+    pub fn sort_by(&mut self, sort: Sort) {
+        self.sort = sort;
+        self.update_rows();
+        self.offset = 0;
+        self.select(self.next_selectable(0, true).unwrap_or(0));
+    }
+
+    /// List hosts under different headers, keeping the selected host.
+    pub fn group_by(&mut self, view: View) {
+        self.view = view;
+        self.update_rows();
+    }
+
+    /// Rebuild the list of rows. While searching it's just the hosts
+    /// that match, best first. Otherwise it's every host, in the
+    /// current sort order and under its group's header, with the
+    /// selected row still selected.
+    fn update_rows(&mut self) {
+        let selected = self.rows.get(self.selected).cloned();
+        self.query = Query::parse(&self.input);
+        let tag = self.tag.as_ref();
+        let tagged = |i: &usize| {
+            tag.is_none_or(|tag| {
+                self.hosts
+                    .get_index(*i)
+                    .is_some_and(|(_, h)| h.tags.contains(tag))
+            })
+        };
+
+        if !self.input.is_empty() {
+            self.rows = search::matches(&self.matcher, &self.hosts, &self.input, &self.frecency)
+                .into_iter()
+                .filter(tagged)
+                .map(Row::Host)
+                .collect();
+            self.status = if self.rows.is_empty() {
+                SearchStatus::Missed
+            } else {
+                SearchStatus::Found
+            };
+            self.selected = 0;
+            self.offset = 0;
+            return;
+        }
+
+        let mut order = (0..self.hosts.len()).filter(tagged).collect::<Vec<_>>();
+        if self.sort == Sort::Frecency {
+            // stable, so hosts we've never launched stay in config order
+            let scores = self
+                .hosts
+                .keys()
+                .map(|alias| self.frecency.get(alias).copied().unwrap_or(0))
+                .collect::<Vec<_>>();
+            order.sort_by_key(|&i| Reverse(scores[i]));
+        }
+        self.rows = self.group_rows(order);
+
+        self.selected = 0;
+        self.offset = 0;
+        match self
+            .rows
+            .iter()
+            .position(|row| Some(row) == selected.as_ref())
+        {
+            Some(i) => self.select(i),
+            None => self.select(self.next_selectable(0, true).unwrap_or(0)),
+        }
+    }
+
+    /// Hosts without a group first, then each group under a header in
+    /// the order they first appear. Collapsed groups only get their
+    /// header.
+    fn group_rows(&self, order: Vec<usize>) -> Vec<Row> {
+        let groups = self
+            .hosts
+            .values()
+            .map(|host| self.group_of(host))
+            .collect::<Vec<_>>();
+        let group = |i: usize| groups[i].as_ref();
+        let mut rows = order
+            .iter()
+            .filter(|&&i| group(i).is_none())
+            .map(|&i| Row::Host(i))
+            .collect::<Vec<_>>();
+        let mut groups: Vec<&String> = vec![];
+        for &i in &order {
+            if let Some(name) = group(i) {
+                if !groups.contains(&name) {
+                    groups.push(name);
+                }
+            }
+        }
+        for name in groups {
+            rows.push(Row::Header(name.clone()));
+            if !self.collapsed.contains(name) {
+                rows.extend(
+                    order
+                        .iter()
+                        .filter(|&&i| group(i) == Some(name))
+                        .map(|&i| Row::Host(i)),
+                );
+            }
+        }
+        rows
+    }
+
+    /// The header a host goes under in the current view, if any.
+    fn group_of(&self, host: &HostEntry) -> Option<String> {
+        match self.view {
+            View::Flat => None,
+            View::Groups => host.group.clone(),
+            View::Files => {
+                let path = host.source.as_ref()?.path.as_ref()?.display().to_string();
+                match std::env::var("HOME") {
+                    Ok(home) if !home.is_empty() && path.starts_with(&home) => {
+                        Some(format!("~{}", &path[home.len()..]))
+                    }
+                    _ => Some(path),
+                }
+            }
+        }
     }
 
     /// Put the terminal into raw mode, hide the cursor, etc.
-    fn setup_terminal() -> io::Result<RawTerminal<Stdout>> {
+    fn setup_terminal() -> io::Result<MouseTerminal<RawTerminal<Stdout>>> {
         let mut stdout = io::stdout().into_raw_mode()?;
         write!(stdout, "{}", ToAlternateScreen)?;
         write!(stdout, "{}", HideCursor)?;
         write!(stdout, "{}", ClearAll)?;
         write!(stdout, "{}", Goto(1, 1))?;
         stdout.flush()?;
-        Ok(stdout)
+        Ok(MouseTerminal::from(stdout))
     }
 
     /// Restore the terminal to its prior state.
     /// We run this on drop().
     fn cleanup_terminal(&mut self) -> io::Result<()> {
-        self.stdout.suspend_raw_mode()?;
-        write!(self.stdout, "{}", ShowCursor)?;
-        write!(self.stdout, "{}", ToMainScreen)?;
-        self.stdout.flush()?;
+        if let Some(stdout) = &mut self.stdout {
+            stdout.suspend_raw_mode()?;
+            write!(stdout, "{}", ShowCursor)?;
+            write!(stdout, "{}", ToMainScreen)?;
+            stdout.flush()?;
+        }
         Ok(())
     }
 
-    /// Start thread to listen for keyboard events.
-    fn event_thread(&self) -> io::Result<Receiver<Key>> {
+    /// Start thread to listen for keyboard and mouse events.
+    fn event_thread(&self) -> io::Result<Receiver<Event>> {
         let (sender, receiver) = unbounded();
         thread::spawn(move || loop {
-            sender
-                .send(io::stdin().keys().next().unwrap().unwrap())
-                .unwrap()
+            let event = match io::stdin().events().next().unwrap().unwrap() {
+                event::Event::Key(key) => Event::Key(key),
+                event::Event::Mouse(mouse) => Event::Mouse(mouse),
+                event::Event::Unsupported(_) => continue,
+            };
+            sender.send(event).unwrap()
         });
         Ok(receiver)
     }
 
     /// Register signal handler. SIGWINCH (resize) only for now.
-    fn signal_thread(&self) -> io::Result<Receiver<Key>> {
+    fn signal_thread(&self) -> io::Result<Receiver<Event>> {
         let (sender, receiver) = unbounded();
         unsafe {
             signal_hook::register(signal_hook::SIGWINCH, move || {
-                sender.send(Key::F(5)).unwrap()
+                sender.send(Event::Resize).unwrap()
             })
         }?;
 
@@ -112,7 +408,7 @@ impl TUI {
     }
 
     /// Main loop. Returns the host we want to SSH to, if any.
-    pub fn run(&mut self) -> io::Result<Option<String>> {
+    pub fn run(&mut self) -> io::Result<Option<Launch>> {
         let ux_rx = self.event_thread()?;
         let signal_rx = self.signal_thread()?;
 
@@ -127,7 +423,7 @@ impl TUI {
             self.update(Some(event))?;
             match self.mode {
                 Mode::Quit => break,
-                Mode::Launch(ref host) => return Ok(Some(host.clone())),
+                Mode::Launch(ref launch) => return Ok(Some(launch.clone())),
                 _ => self.draw()?,
             }
         }
@@ -135,202 +431,462 @@ impl TUI {
         Ok(None)
     }
 
-    /// Update our state in response to key presses.
-    pub fn update(&mut self, event: Option<Key>) -> io::Result<()> {
-        if event.is_none() {
-            return Ok(());
-        }
+    /// Update our state in response to key presses and the mouse.
+    pub fn update(&mut self, event: Option<Event>) -> io::Result<()> {
+        let key = match event {
+            Some(Event::Key(key)) => key,
+            Some(Event::Mouse(mouse)) => return self.update_mouse(mouse),
+            Some(Event::Resize) => return self.resize(),
+            None => return Ok(()),
+        };
 
-        match event.unwrap() {
-            Key::Ctrl('c') | Key::Esc if self.mode == Mode::Nav => self.mode = Mode::Quit,
-            Key::Char('r') | Key::F(5) if self.mode == Mode::Nav => {
-                self.size = terminal_size()?;
-                // reset offset if the screen grew
-                if self.offset > 0 && self.hosts.len() <= self.size.1 as usize {
-                    self.offset = 0;
-                }
-            }
-            Key::Char(' ') | Key::PageDown => {
-                self.selected += 5;
-                if self.selected > self.hosts.len() - 1 {
-                    self.selected = self.hosts.len() - 1;
-                }
-                self.select(self.selected);
-            }
-            Key::Char('-') | Key::PageUp => {
-                if self.selected > 5 {
-                    self.selected -= 5;
-                } else {
-                    self.selected = 0;
-                }
-                self.select(self.selected);
-            }
-            Key::Up | Key::Ctrl('p') => self.select_prev(),
-            Key::Down | Key::Ctrl('n') => self.select_next(),
-            Key::Char('\n') => {
-                if self.mode == Mode::Search && self.status == SearchStatus::Missed {
-                    // do nothing on a search that doesn't match
-                } else if let Some(host) = self.hosts.iter().nth(self.selected) {
-                    self.mode = Mode::Launch(host.0.clone());
-                } else {
-                    return Err(io::Error::new(io::ErrorKind::Other, "can't find host"));
+        self.message = None;
+        let command = self.keymap.get(&self.mode, key);
+        match self.mode {
+            Mode::Warnings => self.update_warnings(command),
+            Mode::Actions => self.update_actions(key, command)?,
+            Mode::Prompt(_) => self.update_prompt(key, command),
+            Mode::Nav | Mode::Search => match (command, key) {
+                (Some(command), _) => self.run_command(command)?,
+                (None, Key::Alt(c)) => {
+                    if let Some(action) = Action::from_key(c) {
+                        self.launch(action)?;
+                    }
                 }
-            }
-            event if self.mode == Mode::Nav => match event {
-                Key::Char('q') => self.mode = Mode::Quit,
-                Key::Char('i') | Key::Char('s') | Key::Char('/') | Key::Char('f') => {
-                    self.status = SearchStatus::Blank;
-                    self.mode = Mode::Search
+                (None, Key::Char(c)) if self.mode == Mode::Search => {
+                    self.input.push(c);
+                    self.update_rows();
                 }
                 _ => {}
             },
-            event if self.mode == Mode::Search => self.update_input(event),
-            _ => {}
+            Mode::Quit | Mode::Launch(_) => {}
         }
 
         Ok(())
     }
 
-    /// Search mode-specific keybindings.
-    fn update_input(&mut self, event: Key) {
-        match event {
-            Key::Ctrl('c') | Key::Esc => {
-                self.status = SearchStatus::Blank;
+    /// Do what a key is bound to in Nav or Search mode.
+    fn run_command(&mut self, command: Command) -> io::Result<()> {
+        match command {
+            Command::Quit => self.mode = Mode::Quit,
+            Command::Back => {
                 if self.input.is_empty() {
                     self.mode = Mode::Nav;
                 } else {
                     self.input.clear();
+                    self.update_rows();
+                }
+                self.status = SearchStatus::Blank;
+            }
+            Command::Search => {
+                self.status = SearchStatus::Blank;
+                self.mode = Mode::Search
+            }
+            Command::Up => self.select_prev(),
+            Command::Down => self.select_next(),
+            Command::PageUp => self.page_up(),
+            Command::PageDown => self.page_down(),
+            Command::Top => {
+                if let Some(i) = self.next_selectable(0, true) {
+                    self.select(i);
                 }
             }
-            Key::Backspace => {
-                if !self.input.is_empty() {
-                    self.input.truncate(self.input.len() - 1);
-                    self.select_search_host();
+            Command::Bottom => {
+                if let Some(i) = self.next_selectable(self.rows.len(), false) {
+                    self.select(i);
+                }
+            }
+            Command::Refresh => self.resize()?,
+            Command::Warnings if !self.warnings.is_empty() => self.mode = Mode::Warnings,
+            Command::Sort => self.sort_by(match self.sort {
+                Sort::Config => Sort::Frecency,
+                Sort::Frecency => Sort::Config,
+            }),
+            Command::View => self.group_by(match self.view {
+                View::Groups => View::Files,
+                View::Files => View::Flat,
+                View::Flat => View::Groups,
+            }),
+            Command::Preview => self.preview = !self.preview,
+            Command::NextTag => self.next_tag(),
+            Command::Collapse => self.collapse(),
+            Command::Expand => self.expand(),
+            Command::Launch if self.selected_collapsed().is_some() => self.expand(),
+            Command::Launch => self.launch(Action::Ssh)?,
+            Command::Menu if self.host_at(self.selected).is_some() => {
+                self.menu_selected = 0;
+                self.mode = Mode::Actions;
+            }
+            Command::DeleteChar => {
+                if self.input.pop().is_some() {
+                    self.update_rows();
                 }
                 if self.input.is_empty() {
                     self.status = SearchStatus::Blank;
                 }
             }
-            Key::Char(c) => {
-                self.input.push(c);
-                self.select_search_host();
+            Command::DeleteWord => {
+                delete_word(&mut self.input);
+                self.update_rows();
+                if self.input.is_empty() {
+                    self.status = SearchStatus::Blank;
+                }
             }
-            _ => {}
+            Command::Clear => {
+                self.input.clear();
+                self.update_rows();
+                self.status = SearchStatus::Blank;
+            }
+            Command::Warnings | Command::Menu => {}
         }
+        Ok(())
     }
 
-    /// Select a host by index.
-    fn select(&mut self, i: usize) {
-        self.selected = i;
-        self.status = SearchStatus::Found;
-        if !self.is_visible(self.selected) {
-            let rows = self.size.1 as usize - 2;
-            if self.selected == 0 {
-                self.offset = 0;
-            } else if self.selected < self.offset {
-                self.offset = self.selected;
-            } else if self.selected > rows {
-                self.offset = self.selected - rows;
+    /// Action menu keybindings. Each action has a letter, or pick one
+    /// with the arrows and Enter.
+    fn update_actions(&mut self, key: Key, command: Option<Command>) -> io::Result<()> {
+        let actions = Action::all();
+        match (command, key) {
+            (Some(Command::Back), _) => self.back(),
+            (Some(Command::Up), _) => self.menu_selected = self.menu_selected.saturating_sub(1),
+            (Some(Command::Down), _) if self.menu_selected + 1 < actions.len() => {
+                self.menu_selected += 1
+            }
+            (Some(Command::Launch), _) => self.launch(actions[self.menu_selected].clone())?,
+            (None, Key::Char(c)) => {
+                if let Some(action) = Action::from_key(c) {
+                    self.launch(action)?;
+                }
             }
+            _ => {}
         }
+        Ok(())
     }
 
-    /// Is the host at the given index visible on screen?
-    fn is_visible(&self, i: usize) -> bool {
-        i >= self.offset && i < self.offset + (self.size.1 as usize - 1)
+    /// Leave the action menu or prompt, back to searching if we were.
+    fn back(&mut self) {
+        self.mode = if self.input.is_empty() {
+            Mode::Nav
+        } else {
+            Mode::Search
+        };
     }
 
-    /// Select the previous host (up). If we're in search mode, only
-    /// selects a matching host.
-    fn select_prev(&mut self) {
-        if self.mode == Mode::Search && !self.input.is_empty() {
-            let mut i = self.selected;
-            let hosts = self.hosts.iter().map(|(h, _)| h).collect::<Vec<_>>();
-            while i > 0 {
-                i -= 1;
-                if let Some(host) = hosts.get(i) {
-                    if self.host_matches(host, &self.input) {
-                        self.select(i);
-                        return;
+    /// Prompt mode keybindings: a line of input for the action.
+    fn update_prompt(&mut self, key: Key, command: Option<Command>) {
+        match (command, key) {
+            (Some(Command::Back), _) => self.back(),
+            (Some(Command::DeleteChar), _) => {
+                self.prompt.pop();
+            }
+            (Some(Command::DeleteWord), _) => delete_word(&mut self.prompt),
+            (Some(Command::Clear), _) => self.prompt.clear(),
+            (Some(Command::Launch), _) => {
+                if let Mode::Prompt(action) = &self.mode {
+                    if !self.prompt.is_empty() {
+                        let action = action.clone().with_input(&self.prompt);
+                        self.mode = Mode::Launch(Launch {
+                            alias: self.selected_name().to_string(),
+                            action,
+                        });
                     }
                 }
             }
-        } else {
-            if self.selected == 0 {
-                self.selected = self.hosts.len() - 1;
-            } else {
-                self.selected -= 1;
+            (None, Key::Char(c)) => self.prompt.push(c),
+            _ => {}
+        }
+    }
+
+    /// Run an action on the selected host: ask for its input first if
+    /// it needs any, otherwise leave the TUI to launch it. Copying the
+    /// hostname happens right here.
+    fn launch(&mut self, action: Action) -> io::Result<()> {
+        if self.mode == Mode::Search && self.status == SearchStatus::Missed {
+            // do nothing on a search that doesn't match
+            return Ok(());
+        }
+        let alias = match self.host_at(self.selected) {
+            Some((alias, _)) => alias.clone(),
+            None => return Ok(()),
+        };
+
+        if action.prompt().is_some() {
+            self.prompt.clear();
+            self.mode = Mode::Prompt(action);
+        } else if action == Action::CopyHostname {
+            let hostname = self.selected_hostname().to_string();
+            if let Some(stdout) = &mut self.stdout {
+                write!(stdout, "{}", osc52(&hostname))?;
+                stdout.flush()?;
             }
+            self.message = Some(format!("copied {}", hostname));
+            self.back();
+        } else {
+            self.mode = Mode::Launch(Launch { alias, action });
         }
-        if !self.is_visible(self.selected) {
-            let rows = self.size.1 as usize - 2;
-            if self.selected > self.offset && self.selected > rows {
-                self.offset = self.selected - rows;
-            } else {
-                self.offset = self.selected;
+        Ok(())
+    }
+
+    /// Fit the list to the terminal's current size.
+    fn resize(&mut self) -> io::Result<()> {
+        self.size = terminal_size()?;
+        // reset offset if the screen grew
+        if self.offset > 0 && self.rows.len() <= self.size.1 as usize {
+            self.offset = 0;
+        }
+        Ok(())
+    }
+
+    /// Warnings mode-specific keybindings.
+    fn update_warnings(&mut self, command: Option<Command>) {
+        match command {
+            Some(Command::Back) => self.mode = Mode::Nav,
+            Some(Command::Up) => self.warnings_offset = self.warnings_offset.saturating_sub(1),
+            Some(Command::Down) if self.warnings_offset + 1 < self.warnings.len() => {
+                self.warnings_offset += 1
             }
+            _ => {}
         }
     }
 
-    /// Select the previous host (up). If we're in search mode, only
-    /// selects a matching host.
-    fn select_next(&mut self) {
-        if self.mode == Mode::Search && !self.input.is_empty() {
-            let mut i = self.selected;
-            let hosts = self.hosts.iter().map(|(h, _)| h).collect::<Vec<_>>();
-            while i < hosts.len() {
-                i += 1;
-                if let Some(host) = hosts.get(i) {
-                    if self.host_matches(host, &self.input) {
+    /// Clicks and the scroll wheel. A click selects a host, or opens
+    /// or closes a group. A double or middle click connects.
+    fn update_mouse(&mut self, event: MouseEvent) -> io::Result<()> {
+        let (button, x, y) = match event {
+            MouseEvent::Press(button, x, y) => (button, x, y),
+            _ => return Ok(()),
+        };
+        match (&self.mode, button) {
+            (Mode::Nav, _) | (Mode::Search, _) => {}
+            (Mode::Warnings, MouseButton::WheelUp) => {
+                self.update_warnings(Some(Command::Up));
+                return Ok(());
+            }
+            (Mode::Warnings, MouseButton::WheelDown) => {
+                self.update_warnings(Some(Command::Down));
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+
+        match button {
+            MouseButton::WheelUp => self.scroll(false),
+            MouseButton::WheelDown => self.scroll(true),
+            MouseButton::Left | MouseButton::Middle => {
+                let i = match self.row_at(x, y) {
+                    Some(i) => i,
+                    None => return Ok(()),
+                };
+                let last = self.last_click.take();
+                match &self.rows[i] {
+                    Row::Header(name) if self.collapsed.contains(name) => {
                         self.select(i);
-                        return;
+                        self.expand();
+                    }
+                    Row::Header(_) => {
+                        // collapsing works from one of the group's hosts
+                        self.select(i + 1);
+                        self.collapse();
+                    }
+                    Row::Host(_) => {
+                        self.select(i);
+                        let double =
+                            last.is_some_and(|(j, at)| j == i && at.elapsed() < DOUBLE_CLICK);
+                        if button == MouseButton::Middle || double {
+                            self.launch(Action::Ssh)?;
+                        } else {
+                            self.last_click = Some((i, Instant::now()));
+                        }
                     }
                 }
             }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The row drawn at a screen position, if any. The preview pane
+    /// and status bar don't count.
+    fn row_at(&self, x: u16, y: u16) -> Option<usize> {
+        if y == 0 || y >= self.size.1 || x as usize > self.list_width() {
+            return None;
+        }
+        let i = self.offset + y as usize - 1;
+        if i < self.rows.len() {
+            Some(i)
         } else {
-            if self.selected >= self.hosts.len() - 1 {
-                self.selected = 0;
-            } else {
-                self.selected += 1;
-            }
+            None
         }
+    }
+
+    /// Move the list up or down without changing the selection. The
+    /// next key that moves the selection brings it back into view.
+    fn scroll(&mut self, down: bool) {
+        let height = self.size.1 as usize - 1;
+        let bottom = self.rows.len().saturating_sub(height);
+        self.offset = if down {
+            (self.offset + SCROLL_ROWS).min(bottom)
+        } else {
+            self.offset.saturating_sub(SCROLL_ROWS)
+        };
+    }
+
+    /// Select a row by index.
+    fn select(&mut self, i: usize) {
+        self.selected = i;
         if !self.is_visible(self.selected) {
             let rows = self.size.1 as usize - 2;
+            // keep a group's header in view when scrolling up to its first host
+            let top = match i.checked_sub(1).and_then(|j| self.rows.get(j)) {
+                Some(Row::Header(_)) => i - 1,
+                _ => i,
+            };
             if self.selected == 0 {
                 self.offset = 0;
+            } else if top < self.offset {
+                self.offset = top;
             } else if self.selected > rows {
                 self.offset = self.selected - rows;
             }
         }
     }
 
-    /// Checks the current self.input against hostnames to find and
-    /// select a match.
-    fn select_search_host(&mut self) {
-        for (i, (host, _)) in self.hosts.iter().enumerate() {
-            if self.host_matches(host, &self.input) {
-                self.select(i);
-                return;
-            }
+    /// Is the host at the given index visible on screen?
+    fn is_visible(&self, i: usize) -> bool {
+        i >= self.offset && i < self.offset + (self.size.1 as usize - 1)
+    }
+
+    /// Can this row be selected? Hosts can, and so can collapsed
+    /// groups so they can be opened again. Open groups' headers are
+    /// skipped.
+    fn is_selectable(&self, i: usize) -> bool {
+        match self.rows.get(i) {
+            Some(Row::Host(_)) => true,
+            Some(Row::Header(name)) => self.collapsed.contains(name),
+            None => false,
         }
+    }
 
-        self.status = SearchStatus::Missed;
+    /// The first selectable row from `i`, looking forward or back.
+    fn next_selectable(&self, i: usize, forward: bool) -> Option<usize> {
+        if forward {
+            (i..self.rows.len()).find(|&j| self.is_selectable(j))
+        } else {
+            (0..=i.min(self.rows.len()))
+                .rev()
+                .find(|&j| self.is_selectable(j))
+        }
+    }
+
+    /// Move the selection down a page.
+    fn page_down(&mut self) {
+        let target = (self.selected + self.jump).min(self.rows.len().saturating_sub(1));
+        if let Some(i) = self
+            .next_selectable(target, true)
+            .or_else(|| self.next_selectable(target, false))
+        {
+            self.select(i);
+        }
+    }
+
+    /// Move the selection up a page.
+    fn page_up(&mut self) {
+        let target = self.selected.saturating_sub(self.jump);
+        if let Some(i) = self
+            .next_selectable(target, false)
+            .or_else(|| self.next_selectable(target, true))
+        {
+            self.select(i);
+        }
+    }
+
+    /// Select the previous host (up). While searching, that's the
+    /// next best match.
+    fn select_prev(&mut self) {
+        let prev = match self.selected.checked_sub(1) {
+            Some(i) => self.next_selectable(i, false),
+            None => None,
+        };
+        // wrap around to the bottom
+        if let Some(i) = prev.or_else(|| self.next_selectable(self.rows.len(), false)) {
+            self.select(i);
+        }
+    }
+
+    /// Select the next host (down). While searching, that's the next
+    /// worse match.
+    fn select_next(&mut self) {
+        // wrap around to the top
+        if let Some(i) = self
+            .next_selectable(self.selected + 1, true)
+            .or_else(|| self.next_selectable(0, true))
+        {
+            self.select(i);
+        }
+    }
+
+    /// The group whose hosts are hidden under the selected row.
+    fn selected_collapsed(&self) -> Option<&str> {
+        match self.rows.get(self.selected) {
+            Some(Row::Header(name)) if self.collapsed.contains(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Hide the hosts in the selected host's group, leaving its header
+    /// selected.
+    fn collapse(&mut self) {
+        let group = match self.host_at(self.selected) {
+            Some((_, host)) => self.group_of(host),
+            None => None,
+        };
+        if let Some(group) = group {
+            self.collapsed.insert(group.clone());
+            self.rows[self.selected] = Row::Header(group);
+            self.update_rows();
+        }
+    }
+
+    /// Show the hosts in the selected group again, and select the
+    /// first.
+    fn expand(&mut self) {
+        if let Some(group) = self.selected_collapsed().map(String::from) {
+            self.collapsed.remove(&group);
+            self.update_rows();
+            self.select_next();
+        }
+    }
+
+    /// Only show hosts with the next tag, then every host again once
+    /// we've been through them all.
+    fn next_tag(&mut self) {
+        let mut tags = vec![];
+        for host in self.hosts.values() {
+            for tag in &host.tags {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+        let next = match &self.tag {
+            Some(tag) => tags.iter().position(|t| *t == tag).map(|i| i + 1),
+            None => Some(0),
+        };
+        self.tag = next.and_then(|i| tags.get(i)).map(|t| t.to_string());
+        self.update_rows();
     }
 
-    /// Does a given host match our search string? Just a prefix
-    /// match, for now.
-    fn host_matches(&self, host: &str, search: &str) -> bool {
-        self.matcher.fuzzy_match(host, search).is_some()
+    /// The host shown on the given row of the list.
+    fn host_at(&self, row: usize) -> Option<(&String, &HostEntry)> {
+        match self.rows.get(row) {
+            Some(Row::Host(i)) => self.hosts.get_index(*i),
+            _ => None,
+        }
     }
 
     /// The name of the currently selected host pattern.
     fn selected_name(&self) -> &str {
-        if let Some((_, (name, _))) = self
-            .hosts
-            .iter()
-            .enumerate()
-            .find(|(i, _)| *i == self.selected)
-        {
+        if let Some((name, _)) = self.host_at(self.selected) {
             name
         } else {
             "shy"
@@ -340,85 +896,371 @@ impl TUI {
     /// The hostname of the currently selected host pattern. The two
     /// might be different.
     fn selected_hostname(&self) -> &str {
-        if let Some((_, (_, hostname))) = self
-            .hosts
-            .iter()
-            .enumerate()
-            .find(|(i, _)| *i == self.selected)
-        {
-            hostname
+        if let Some((_, entry)) = self.host_at(self.selected) {
+            entry.hostname()
         } else {
             "shy"
         }
     }
 
-    /// (bg, fg) colors for the prompt
-    fn prompt_colors(&self) -> (&str, &str) {
+    /// The search bar's style, for how the search is going.
+    fn prompt_style(&self) -> &Style {
         match self.status {
-            SearchStatus::Blank => (color::WhiteBG.as_ref(), color::Black.as_ref()),
-            SearchStatus::Found => (color::GreenBG.as_ref(), color::Black.as_ref()),
-            SearchStatus::Missed => (color::RedBG.as_ref(), color::White.as_ref()),
+            SearchStatus::Blank => &self.theme.prompt,
+            SearchStatus::Found => &self.theme.found,
+            SearchStatus::Missed => &self.theme.missed,
         }
     }
 
     /// Draw the ui
     pub fn draw(&self) -> io::Result<()> {
-        let (_cols, rows) = self.size;
+        if self.mode == Mode::Warnings {
+            return self.draw_warnings();
+        }
+
+        let (cols, rows) = self.size;
         let mut stdout = io::stdout();
 
-        if self.mode == Mode::Search {
-            let (bg, fg) = self.prompt_colors();
+        if let Mode::Prompt(action) = &self.mode {
+            write!(
+                stdout,
+                "{}{}{}{}{}{}{}",
+                ClearAll,
+                Goto(1, rows),
+                self.theme.prompt,
+                ClearLine,
+                action.prompt().unwrap_or_default(),
+                self.prompt,
+                color!(Reset),
+            )?;
+        } else if self.mode == Mode::Search {
+            let style = self.prompt_style();
             write!(
                 stdout,
-                "{}{}{}{}{}{}{}{}",
+                "{}{}{}{}>> {}{}",
                 ClearAll,
                 Goto(1, rows),
-                bg,
-                fg,
+                style,
                 ClearLine,
-                ">> ",
                 self.highlight_matches()?,
                 color!(Reset),
             )?;
+            // say how the search is going, not just with color
+            let mut right = match self.status {
+                SearchStatus::Blank => String::new(),
+                SearchStatus::Found => format!(
+                    "✓ {} match{} ",
+                    self.rows.len(),
+                    if self.rows.len() == 1 { "" } else { "es" }
+                ),
+                SearchStatus::Missed => "✗ no match ".into(),
+            };
+            if !self.ssh_args.is_empty() {
+                right.push_str(&format!("-- {} ", self.ssh_args_string()));
+            }
+            if !right.is_empty() {
+                write!(
+                    stdout,
+                    "{}{}",
+                    Goto(cols.saturating_sub(right.chars().count() as u16) + 1, rows),
+                    style.paint(&right)
+                )?;
+            }
         } else {
             write!(
                 stdout,
-                "{}{}{}{}{}{}",
+                "{}{}{}{}{}",
                 ClearAll,
                 Goto(1, rows),
-                color!(MagentaBG),
-                color!(Yellow),
+                self.theme.status,
                 ClearLine,
-                color_string!(self.selected_hostname(), MagentaBG, Yellow, Bold)
+                self.theme
+                    .status
+                    .bold()
+                    .paint(self.message.as_deref().unwrap_or(self.selected_hostname()))
             )?;
+            if !self.ssh_args.is_empty() {
+                write!(
+                    stdout,
+                    " {}",
+                    self.theme.status.paint(&self.ssh_args_string())
+                )?;
+            }
+            let mut right = String::new();
+            if let Some(tag) = &self.tag {
+                right.push_str(&format!("#{} (t) ", tag));
+            }
+            if self.sort == Sort::Frecency {
+                right.push_str("recent first (o) ");
+            }
+            match self.view {
+                View::Files => right.push_str("by file (g) "),
+                View::Flat => right.push_str("flat (g) "),
+                View::Groups => {}
+            }
+            if !self.warnings.is_empty() {
+                right.push_str(&format!(
+                    "{} warning{} (w) ",
+                    self.warnings.len(),
+                    if self.warnings.len() == 1 { "" } else { "s" }
+                ));
+            }
+            if !right.is_empty() {
+                write!(
+                    stdout,
+                    "{}{}",
+                    Goto(cols.saturating_sub(right.chars().count() as u16) + 1, rows),
+                    self.theme.status.paint(&right)
+                )?;
+            }
         }
 
-        let mut row = 1;
-        for (i, (host, _config)) in self.hosts.iter().enumerate().skip(self.offset) {
+        let grouped = self.rows.iter().any(|row| matches!(row, Row::Header(_)));
+        for (row, i) in (1..).zip(self.offset..self.rows.len()) {
             if i >= self.offset + (rows as usize - 1) {
                 break;
             }
+            let (host, entry) = match &self.rows[i] {
+                Row::Header(name) => {
+                    self.draw_header(row, name, i == self.selected)?;
+                    continue;
+                }
+                Row::Host(i) => match self.hosts.get_index(*i) {
+                    Some(host) => host,
+                    None => break,
+                },
+            };
+            let (style, field_style) = if i == self.selected {
+                (&self.theme.selection, &self.theme.selection)
+            } else {
+                (&self.theme.host, &self.theme.dim)
+            };
+            // hosts under a header are indented
+            let indent = if grouped && self.group_of(entry).is_some() {
+                "  "
+            } else {
+                ""
+            };
+            let width = self
+                .list_width()
+                .saturating_sub(host.chars().count() + indent.len() + 6);
+            // a match ends with a reset, so the row's style comes back
+            let on = self.theme.matched.to_string();
+            let offs = [
+                format!("{}{}", color!(Reset), style),
+                format!("{}{}", color!(Reset), field_style),
+            ];
+            let (name, field) = self.highlight_host(host, entry, &on, [&offs[0], &offs[1]], width);
 
             write!(
                 stdout,
-                "{}{}",
+                "{}{} {}{}",
                 Goto(1, row),
-                if i == self.selected {
-                    format!("> {}", color_string!(host, Yellow, Bold))
+                if i == self.selected { ">" } else { " " },
+                indent,
+                style.paint(&name)
+            )?;
+            // show which field matched, if it wasn't the alias
+            if let Some(field) = field {
+                write!(stdout, "  {}", field_style.paint(&field))?;
+            }
+        }
+
+        if self.preview_width() > 0 {
+            self.draw_preview()?;
+        }
+
+        if self.mode == Mode::Actions {
+            self.draw_actions()?;
+        }
+
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Draw a group's name, how many hosts are in it, and whether
+    /// they're hidden.
+    fn draw_header(&self, row: u16, name: &str, selected: bool) -> io::Result<()> {
+        let tag = self.tag.as_ref();
+        let count = self
+            .hosts
+            .values()
+            .filter(|h| self.group_of(h).as_deref() == Some(name))
+            .filter(|h| tag.is_none_or(|tag| h.tags.contains(tag)))
+            .count();
+        let collapsed = self.collapsed.contains(name);
+        let header = format!("{} {} ({})", if collapsed { "▸" } else { "▾" }, name, count);
+        write!(
+            io::stdout(),
+            "{}{}",
+            Goto(1, row),
+            if selected {
+                format!("> {}", self.theme.selection.paint(&header))
+            } else {
+                format!("  {}", self.theme.header.paint(&header))
+            }
+        )
+    }
+
+    /// How many columns the preview pane gets: none if it's off or the
+    /// terminal is too narrow.
+    fn preview_width(&self) -> usize {
+        if self.preview && self.size.0 as usize >= PREVIEW_WIDTH + MIN_LIST_WIDTH {
+            PREVIEW_WIDTH
+        } else {
+            0
+        }
+    }
+
+    /// How many columns the host list gets.
+    fn list_width(&self) -> usize {
+        (self.size.0 as usize).saturating_sub(self.preview_width())
+    }
+
+    /// Draw the selected host's details down the right side, above
+    /// the status bar.
+    fn draw_preview(&self) -> io::Result<()> {
+        let (_, rows) = self.size;
+        let mut stdout = io::stdout();
+        let left = self.list_width() as u16 + 1;
+        let width = PREVIEW_WIDTH - 2;
+
+        let mut lines = vec![];
+        if let Some((name, entry)) = self.host_at(self.selected) {
+            lines.push(color_string!(truncate(name, width), Bold));
+            if let Some(desc) = &entry.desc {
+                lines.push(truncate(desc, width).into_owned());
+            }
+            lines.push(String::new());
+            for (label, value) in preview::lines(&self.hosts, entry) {
+                let value = if label == "Source" {
+                    // the line number matters more than the start of the path
+                    truncate_start(&value, width.saturating_sub(15))
+                } else {
+                    truncate(&value, width.saturating_sub(15))
+                };
+                lines.push(format!(
+                    "{} {}",
+                    self.theme.dim.paint(&format!("{:14}", label)),
+                    value
+                ));
+            }
+        }
+
+        // blank each row first, to cover any long host names
+        let blank = " ".repeat(width);
+        for row in 1..rows {
+            write!(stdout, "{}│ {}", Goto(left, row), blank)?;
+            if let Some(line) = lines.get(row as usize - 1) {
+                write!(stdout, "{}{}", Goto(left + 2, row), line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the action menu as a box in the middle of the screen.
+    fn draw_actions(&self) -> io::Result<()> {
+        let (cols, rows) = self.size;
+        let mut stdout = io::stdout();
+        let title = self.selected_name();
+        let actions = Action::all();
+        let width = actions
+            .iter()
+            .map(|a| a.label().len() + 6)
+            .chain(Some(title.chars().count() + 4))
+            .max()
+            .unwrap_or(0);
+        let left = (cols as usize).saturating_sub(width + 2) / 2 + 1;
+        let top = (rows as usize).saturating_sub(actions.len() + 2) / 2 + 1;
+
+        let border = "─".repeat(width.saturating_sub(title.chars().count() + 3));
+        write!(
+            stdout,
+            "{}┌─ {} {}┐",
+            Goto(left as u16, top as u16),
+            color_string!(title, Bold),
+            border
+        )?;
+        for (i, action) in actions.iter().enumerate() {
+            let item = format!(" {}  {:w$}", action.key(), action.label(), w = width - 4);
+            write!(
+                stdout,
+                "{}│{}│",
+                Goto(left as u16, (top + i + 1) as u16),
+                if i == self.menu_selected {
+                    self.theme.selection.paint(&item)
                 } else {
-                    format!("  {}", color_string!(host, White))
+                    item
                 }
             )?;
-            row += 1;
+        }
+        write!(
+            stdout,
+            "{}└{}┘",
+            Goto(left as u16, (top + actions.len() + 1) as u16),
+            "─".repeat(width)
+        )?;
+        Ok(())
+    }
+
+    /// The extra ssh arguments as you'd type them in a shell, so it's
+    /// clear what will run.
+    fn ssh_args_string(&self) -> String {
+        self.ssh_args
+            .iter()
+            .map(|arg| {
+                if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '\'') {
+                    format!("'{}'", arg.replace('\'', "'\\''"))
+                } else {
+                    arg.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Draw the list of config lines we skipped.
+    fn draw_warnings(&self) -> io::Result<()> {
+        let (cols, rows) = self.size;
+        let mut stdout = io::stdout();
+
+        write!(
+            stdout,
+            "{}{}{}{}{}",
+            ClearAll,
+            Goto(1, rows),
+            self.theme.status,
+            ClearLine,
+            self.theme.status.bold().paint(&format!(
+                "{} config lines skipped. ESC to go back.",
+                self.warnings.len()
+            ))
+        )?;
+
+        // each warning takes two rows: where it is, then the line itself
+        let lines = self
+            .warnings
+            .iter()
+            .skip(self.warnings_offset)
+            .flat_map(|w| {
+                vec![
+                    self.theme.warning.paint(&w.to_string()),
+                    format!("    {}", w.text.trim()),
+                ]
+            });
+        for (row, line) in (1..rows).zip(lines) {
+            let line = line.chars().take(cols as usize).collect::<String>();
+            write!(stdout, "{}{}", Goto(1, row), line)?;
         }
 
         stdout.flush()?;
         Ok(())
     }
 
-    /// Highlight (embolden) the matching letters in a host, which may
-    /// not be consecutive since we use fuzzy finding.
-    fn highlight_matches(&self) -> io::Result<Cow<str>> {
+    /// Highlight (embolden) the matching letters in the selected host,
+    /// which may not be consecutive since we use fuzzy finding. If
+    /// another field matched, like the hostname, show that too.
+    fn highlight_matches(&self) -> io::Result<Cow<'_, str>> {
         if self.input.is_empty() {
             return Ok(Cow::from(""));
         }
@@ -427,24 +1269,113 @@ impl TUI {
             return Ok(Cow::from(&self.input));
         }
 
-        let mut out = String::new();
-        let mut host = self.selected_name();
-        for c in self.input.chars() {
-            if let Some(idx) = host.find(c) {
-                if idx > 0 {
-                    out.push_str(&host[..idx]);
+        let (on, off) = (color!(Bold), "\x1b[22m");
+        match self.host_at(self.selected) {
+            Some((name, entry)) => {
+                match self.highlight_host(name, entry, on, [off, off], usize::MAX) {
+                    (name, Some(field)) => Ok(Cow::from(format!("{} {}", name, field))),
+                    (name, None) => Ok(Cow::from(name)),
                 }
-                out.push_str("\x1b[1m");
-                out.push(c);
-                out.push_str("\x1b[22m");
-                host = &host[idx + 1..];
             }
+            None => Ok(Cow::from(&self.input)),
+        }
+    }
+
+    /// A host's name with the matching chars wrapped in `on` and
+    /// `offs[0]`, plus the first other field that matched, wrapped in
+    /// `on` and `offs[1]` and cut to `width`.
+    fn highlight_host(
+        &self,
+        name: &str,
+        host: &HostEntry,
+        on: &str,
+        offs: [&str; 2],
+        width: usize,
+    ) -> (String, Option<String>) {
+        let found = match self.find(host) {
+            Some(found) => found,
+            None => return (name.to_string(), None),
+        };
+        let name = highlight(
+            name,
+            found.indices(Field::Alias).unwrap_or_default(),
+            on,
+            offs[0],
+        );
+        let field = found
+            .fields
+            .iter()
+            .find(|(field, _)| *field != Field::Alias)
+            .map(|(field, indices)| {
+                let text = field.text(host).unwrap_or_default();
+                let text = truncate(&text, width);
+                format!(
+                    "{}{}",
+                    field.prefix(),
+                    highlight(&text, indices, on, offs[1])
+                )
+            });
+        (name, field)
+    }
+
+    /// How a host matches the search, if it does.
+    fn find(&self, host: &HostEntry) -> Option<Match> {
+        if self.input.is_empty() {
+            return None;
         }
-        if !host.is_empty() {
-            out.push_str(host);
+        self.query.find(&self.matcher, host)
+    }
+}
+
+/// Delete the last word of `text`, and any spaces after it, like
+/// Ctrl-w in a shell.
+fn delete_word(text: &mut String) {
+    let trimmed = text.trim_end_matches(' ').len();
+    let start = text[..trimmed].rfind(' ').map(|i| i + 1).unwrap_or(0);
+    text.truncate(start);
+}
+
+/// Cut `text` down to `width` chars, with an ellipsis if anything was
+/// cut.
+fn truncate(text: &str, width: usize) -> Cow<'_, str> {
+    if text.chars().count() <= width {
+        Cow::from(text)
+    } else {
+        let mut out = text
+            .chars()
+            .take(width.saturating_sub(1))
+            .collect::<String>();
+        out.push('…');
+        Cow::from(out)
+    }
+}
+
+/// Like `truncate`, but cut from the start instead.
+fn truncate_start(text: &str, width: usize) -> Cow<'_, str> {
+    let count = text.chars().count();
+    if count <= width {
+        Cow::from(text)
+    } else {
+        let mut out = String::from("…");
+        out.extend(text.chars().skip(count + 1 - width.max(1)));
+        Cow::from(out)
+    }
+}
+
+/// Wrap the chars at `indices` in `on` and `off`, which should only
+/// change one attribute so the text keeps the rest of its color.
+fn highlight(text: &str, indices: &[usize], on: &str, off: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if indices.contains(&i) {
+            out.push_str(on);
+            out.push(c);
+            out.push_str(off);
+        } else {
+            out.push(c);
         }
-        Ok(Cow::from(out))
     }
+    out
 }
 
 /// Try to always clean up the terminal.
@@ -453,3 +1384,169 @@ impl Drop for TUI {
         let _ = self.cleanup_terminal();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ssh_config::parse_ssh_config};
+
+    /// A TUI with five list rows and no terminal, over hosts that are
+    /// all in a group so the list starts with a header.
+    fn tui() -> TUI {
+        let hosts = parse_ssh_config(
+            "# shy: group=EU\nHost eu1\n# shy: group=EU\nHost eu2\n# shy: group=EU\nHost eu3\n\
+             # shy: group=US\nHost us1\n# shy: group=US\nHost us2\n\
+             # shy: group=DB\nHost db1\n",
+        )
+        .unwrap();
+        TUI::with_terminal(hosts, vec![], &Settings::default(), (80, 6), None)
+    }
+
+    fn click(tui: &mut TUI, button: MouseButton, y: u16) {
+        tui.update(Some(Event::Mouse(MouseEvent::Press(button, 1, y))))
+            .unwrap();
+    }
+
+    fn run(tui: &mut TUI, command: Command) -> usize {
+        tui.run_command(command).unwrap();
+        tui.selected
+    }
+
+    #[test]
+    fn test_up_down() {
+        let mut tui = tui();
+        assert_eq!(1, tui.selected); // not the EU header
+        assert_eq!(8, run(&mut tui, Command::Up)); // wraps to the bottom
+        assert_eq!(1, run(&mut tui, Command::Down)); // and back to the top
+        tui.select(3);
+        assert_eq!(5, run(&mut tui, Command::Down)); // over the US header
+        assert_eq!(3, run(&mut tui, Command::Up));
+
+        // a closed group's header can be selected, here as the last row
+        tui.select(8);
+        tui.collapse();
+        assert_eq!(Row::Header("DB".into()), tui.rows[7]);
+        assert_eq!(8, tui.rows.len());
+        assert_eq!(7, tui.selected);
+        assert_eq!(1, run(&mut tui, Command::Down));
+        assert_eq!(7, run(&mut tui, Command::Up));
+        assert_eq!(6, run(&mut tui, Command::Up));
+
+        // and as the first
+        tui.select(1);
+        tui.collapse();
+        assert_eq!(Row::Header("EU".into()), tui.rows[0]);
+        assert_eq!(0, tui.selected);
+        assert_eq!(4, run(&mut tui, Command::Up));
+        assert_eq!(0, run(&mut tui, Command::Down));
+        assert_eq!(2, run(&mut tui, Command::Down)); // under the open US header
+
+        tui.select(0);
+        assert_eq!(1, run(&mut tui, Command::Expand)); // EU's first host
+        assert_eq!(Row::Host(0), tui.rows[1]);
+    }
+
+    #[test]
+    fn test_page_up_down() {
+        let mut tui = tui();
+        assert_eq!(5, tui.jump);
+        assert_eq!(6, run(&mut tui, Command::PageDown));
+        assert_eq!(8, run(&mut tui, Command::PageDown)); // stops at the end
+        assert_eq!(8, run(&mut tui, Command::PageDown));
+        assert_eq!(3, run(&mut tui, Command::PageUp));
+        assert_eq!(1, run(&mut tui, Command::PageUp)); // not the EU header
+        tui.select(2);
+        assert_eq!(8, run(&mut tui, Command::PageDown)); // past the DB header
+
+        // with the first and last groups closed, their headers are stops
+        tui.collapse();
+        tui.select(1);
+        tui.collapse();
+        assert_eq!(
+            vec![
+                Row::Header("EU".into()),
+                Row::Header("US".into()),
+                Row::Host(3),
+                Row::Host(4),
+                Row::Header("DB".into()),
+            ],
+            tui.rows
+        );
+        assert_eq!(0, tui.selected);
+        assert_eq!(4, run(&mut tui, Command::PageDown));
+        assert_eq!(0, run(&mut tui, Command::PageUp));
+        tui.select(2);
+        assert_eq!(0, run(&mut tui, Command::PageUp));
+    }
+
+    #[test]
+    fn test_row_at() {
+        let mut tui = tui();
+        assert_eq!(Row::Header("EU".into()), tui.rows[0]);
+        assert_eq!(Some(0), tui.row_at(1, 1));
+        assert_eq!(Some(4), tui.row_at(80, 5));
+        assert_eq!(None, tui.row_at(1, 0)); // above the list
+        assert_eq!(None, tui.row_at(1, 6)); // the status bar
+        assert_eq!(None, tui.row_at(81, 1));
+
+        tui.offset = 4;
+        assert_eq!(Some(4), tui.row_at(1, 1));
+        assert_eq!(Some(8), tui.row_at(1, 5));
+        tui.offset = 6;
+        assert_eq!(Some(8), tui.row_at(1, 3));
+        assert_eq!(None, tui.row_at(1, 4)); // past the last row
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut tui = tui();
+        click(&mut tui, MouseButton::WheelDown, 1);
+        assert_eq!(3, tui.offset);
+        click(&mut tui, MouseButton::WheelDown, 1);
+        assert_eq!(4, tui.offset); // the last row is at the bottom
+        assert_eq!(1, tui.selected);
+        click(&mut tui, MouseButton::WheelUp, 1);
+        assert_eq!(1, tui.offset);
+        click(&mut tui, MouseButton::WheelUp, 1);
+        assert_eq!(0, tui.offset);
+
+        // the wheel scrolls warnings, not the hidden list
+        tui.offset = 2;
+        tui.mode = Mode::Warnings;
+        click(&mut tui, MouseButton::WheelDown, 1);
+        click(&mut tui, MouseButton::WheelUp, 1);
+        assert_eq!(2, tui.offset);
+    }
+
+    #[test]
+    fn test_click() {
+        let mut tui = tui();
+        tui.offset = 4;
+        click(&mut tui, MouseButton::Left, 3); // us2
+        assert_eq!(6, tui.selected);
+        assert_eq!(Mode::Nav, tui.mode);
+
+        // an open group's header closes it
+        click(&mut tui, MouseButton::Left, 1);
+        assert_eq!(Row::Header("US".into()), tui.rows[4]);
+        assert_eq!(Row::Header("DB".into()), tui.rows[5]);
+        assert_eq!(4, tui.selected);
+
+        // and a closed one opens it, selecting its first host
+        assert_eq!(0, tui.offset);
+        click(&mut tui, MouseButton::Left, 5);
+        assert_eq!(Row::Host(3), tui.rows[5]);
+        assert_eq!(5, tui.selected);
+        assert_eq!(1, tui.offset);
+
+        click(&mut tui, MouseButton::WheelDown, 1);
+        assert_eq!(4, tui.offset);
+        click(&mut tui, MouseButton::Middle, 3); // us2 again
+        assert_eq!(
+            Mode::Launch(Launch {
+                alias: "us2".into(),
+                action: Action::Ssh
+            }),
+            tui.mode
+        );
+    }
+}